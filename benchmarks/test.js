@@ -46,3 +46,31 @@ const ssbKeysNeonDuration = Math.round((ne1 + ne2 + ne3) / 3);
 const speedup = ((100 * ssbKeysNeonDuration) / ssbKeysDuration).toFixed(1);
 console.log(`ssb-keys      ran in ${ssbKeysDuration}ms`);
 console.log(`ssb-neon-keys ran in ${ssbKeysNeonDuration}ms (${speedup}%)`);
+
+function verifyEach(keys, objs) {
+  const before = Date.now();
+  for (const obj of objs) ssbKeysNeon.verifyObj(keys, obj);
+  return Date.now() - before;
+}
+
+function verifyBatch(keys, objs) {
+  const before = Date.now();
+  ssbKeysNeon.verifyObjBatch(keys, null, objs);
+  return Date.now() - before;
+}
+
+const author = ssbKeysNeon.generate();
+const objs = [];
+for (let i = 0; i < 10e3; i++) {
+  objs.push(ssbKeysNeon.signObj(author, {type: 'post', text: 'hello ' + i}));
+}
+verifyBatch(author, objs); // warm up the CPU
+const eachDuration = Math.round(
+  (verifyEach(author, objs) + verifyEach(author, objs) + verifyEach(author, objs)) / 3,
+);
+const batchDuration = Math.round(
+  (verifyBatch(author, objs) + verifyBatch(author, objs) + verifyBatch(author, objs)) / 3,
+);
+const batchSpeedup = ((100 * batchDuration) / eachDuration).toFixed(1);
+console.log(`verifyObj x ${objs.length}   ran in ${eachDuration}ms`);
+console.log(`verifyObjBatch     ran in ${batchDuration}ms (${batchSpeedup}%)`);
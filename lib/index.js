@@ -1,4 +1,68 @@
-module.exports = require('neon-load-or-build')({
+const native = require('neon-load-or-build')({
   moduleName: 'ssb-keys-neon',
   dir: __dirname + '/..',
 });
+
+// Calls `fn` with a callback at `index` (or last), settling the promise
+function callWithPromise(fn, args, index) {
+  if (index !== undefined) args.length = index;
+  let ret;
+  const promise = new Promise((resolve, reject) => {
+    ret = fn(...args, (err, value) => (err ? reject(err) : resolve(value)));
+  });
+  return [promise, ret];
+}
+
+// The native `*Async` functions and `validateFeeds` take a callback as their
+// last argument, and throwing on bad arguments becomes a rejection
+function promisify(fn) {
+  return (...args) => callWithPromise(fn, args)[0];
+}
+
+// Callback functions return a promise when the callback is left out. `index`
+// is where the callback goes for those that don't just take it last
+function orPromise(fn, index) {
+  return (...args) => {
+    const i = index === undefined ? args.length - 1 : index;
+    if (typeof args[i] === 'function') return fn(...args);
+    return callWithPromise(fn, args, index)[0];
+  };
+}
+
+const asyncFunctions = [
+  'signAsync',
+  'verifyAsync',
+  'signObjAsync',
+  'verifyObjAsync',
+  'verifyObjBatchAsync',
+  'hashAsync',
+  'boxAsync',
+  'unboxAsync',
+  'unboxKeyAsync',
+  'unboxBodyAsync',
+  'secretBoxAsync',
+  'secretUnboxAsync',
+  'validateFeeds',
+];
+
+const wrapped = {
+  load: orPromise(native.load),
+  create: orPromise(native.create),
+  loadOrCreate: orPromise(native.loadOrCreate),
+  generateMany: orPromise(native.generateMany),
+  createEncrypted: orPromise(native.createEncrypted, 2),
+  loadEncrypted: orPromise(native.loadEncrypted, 2),
+  changePassphrase: orPromise(native.changePassphrase, 3),
+  // The promise also carries the search id, for `cancelVanity`
+  generateVanity(...args) {
+    if (typeof args[args.length - 1] === 'function') {
+      return native.generateVanity(...args);
+    }
+    const [promise, id] = callWithPromise(native.generateVanity, args);
+    promise.id = id;
+    return promise;
+  },
+};
+for (const name of asyncFunctions) wrapped[name] = promisify(native[name]);
+
+module.exports = Object.assign({}, native, wrapped);
@@ -0,0 +1,129 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Same mode that JS ssb-keys gives to the keyfiles it creates
+#[cfg(unix)]
+const KEYFILE_MODE: u32 = 0o400;
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = OsString::from(".");
+  name.push(path.file_name().unwrap_or_default());
+  name.push(suffix);
+  path.with_file_name(name)
+}
+
+// Makes a rename or link in `dir` durable
+fn sync_dir(dir: &Path) -> io::Result<()> {
+  #[cfg(unix)]
+  File::open(dir)?.sync_all()?;
+  #[cfg(not(unix))]
+  let _ = dir;
+  Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+  match path.parent() {
+    Some(dir) if !dir.as_os_str().is_empty() => dir,
+    _ => Path::new("."),
+  }
+}
+
+// Writes and fsyncs `contents` to a fresh temp file next to `path`,
+// which only the current user can read
+pub fn write_temp(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+  fs::create_dir_all(parent_dir(path))?;
+  let mut random = [0u8; 8];
+  getrandom::getrandom(&mut random).map_err(io::Error::from)?;
+  let suffix = random
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect::<String>();
+  let tmp_path = sibling_path(path, &format!(".tmp-{}", suffix));
+
+  let mut options = OpenOptions::new();
+  options.create_new(true).write(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(KEYFILE_MODE);
+  }
+  let result = options.open(&tmp_path).and_then(|mut file| {
+    file.write_all(contents)?;
+    file.sync_all()
+  });
+  if let Err(e) = result {
+    let _ = fs::remove_file(&tmp_path);
+    return Err(e);
+  }
+  Ok(tmp_path)
+}
+
+// Creates a new keyfile, failing if one already exists. Readers either see no
+// file or the complete file, even if the process dies in the middle
+pub fn write_keyfile(path: &Path, contents: &[u8]) -> io::Result<()> {
+  let tmp_path = write_temp(path, contents)?;
+  // Unlike rename, linking never replaces an existing keyfile. Once it is
+  // linked or not, a temp file left behind is only litter
+  let linked = fs::hard_link(&tmp_path, path);
+  let _ = fs::remove_file(&tmp_path);
+  linked?;
+  sync_dir(parent_dir(path))
+}
+
+// Atomically replaces the keyfile at `path` with `contents`
+pub fn replace_keyfile(path: &Path, contents: &[u8]) -> io::Result<()> {
+  let tmp_path = write_temp(path, contents)?;
+  rename_temp(&tmp_path, path)
+}
+
+// Atomically replaces the keyfile at `path` with a file from `write_temp`,
+// removing the temp file if that fails
+pub fn rename_temp(tmp_path: &Path, path: &Path) -> io::Result<()> {
+  if let Err(e) = fs::rename(tmp_path, path) {
+    let _ = fs::remove_file(tmp_path);
+    return Err(e);
+  }
+  sync_dir(parent_dir(path))
+}
+
+// Gives the keyfile at `path` a second name, failing if `link` exists
+pub fn link_keyfile(path: &Path, link: &Path) -> io::Result<()> {
+  fs::hard_link(path, link)?;
+  sync_dir(parent_dir(link))
+}
+
+// An advisory lock on `<dir>/.<keyfile>.lock`, held until dropped. It only
+// excludes other callers that also take the lock, like `loadOrCreate` does,
+// and only on unix. Elsewhere it does nothing, and callers must still cope
+// with a keyfile created in the meantime
+pub struct KeyfileLock {
+  _file: File,
+}
+
+impl KeyfileLock {
+  pub fn acquire(path: &Path) -> io::Result<KeyfileLock> {
+    fs::create_dir_all(parent_dir(path))?;
+    let file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(sibling_path(path, ".lock"))?;
+    #[cfg(unix)]
+    {
+      use std::os::unix::io::AsRawFd;
+      loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+          break;
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+          return Err(e);
+        }
+      }
+    }
+    // Closing the file releases the lock
+    Ok(KeyfileLock { _file: file })
+  }
+}
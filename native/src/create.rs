@@ -0,0 +1,219 @@
+use super::errors::{self, throw_code, CodedError};
+use super::hash;
+use super::legacy_json;
+use super::sig::{self, hmac_key_arg};
+use super::utils::{self, ContextExt, HandleExt, StringExt, ValueExt};
+use super::validate::{self, FeedState};
+use neon::prelude::*;
+use ssb_crypto::{Keypair, NetworkKey as AuthKey};
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// New legacy messages, laid out like ssb-validate's `create` does and checked
+// so that `validate` accepts them
+
+fn now_millis() -> f64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as f64)
+    .unwrap_or(0.0)
+}
+
+// A feed being appended to
+struct Feed {
+  keypair: Keypair,
+  hmac_key: Option<AuthKey>,
+  author: String,
+  state: Option<FeedState>,
+}
+
+impl Feed {
+  // Returns `{key, value, state}`, where `state` is what `validate` would
+  // return for the message
+  fn append<'a>(
+    &mut self,
+    cx: &mut FunctionContext<'a>,
+    content: Handle<'a, JsValue>,
+    timestamp: Option<f64>,
+    content_index: i32,
+    timestamp_index: i32,
+  ) -> JsResult<'a, JsObject> {
+    let checked = validate::read_content(cx, content)?;
+    if let Err(err) = validate::check_content(&checked) {
+      return err.arg(content_index).throw(cx);
+    }
+
+    // Timestamps increase within a feed, even when the clock goes back
+    let latest = self.state.as_ref().map(|state| state.timestamp);
+    let timestamp = match timestamp {
+      Some(timestamp) if latest.is_some_and(|latest| timestamp <= latest) => {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          "timestamp must be later than that of the latest message in the feed",
+        )
+        .arg(timestamp_index)
+        .throw(cx)
+      }
+      Some(timestamp) => timestamp,
+      None => latest.map_or_else(now_millis, |latest| now_millis().max(latest + 1.0)),
+    };
+    let sequence = self
+      .state
+      .as_ref()
+      .map_or(1.0, |state| state.sequence + 1.0);
+
+    let value = cx.empty_object();
+    let previous = match &self.state {
+      Some(state) => cx.string(&state.id).upcast::<JsValue>(),
+      None => cx.null().upcast(),
+    };
+    value.set(cx, "previous", previous)?;
+    let author = cx.string(&self.author);
+    value.set(cx, "author", author)?;
+    let sequence_val = cx.number(sequence);
+    value.set(cx, "sequence", sequence_val)?;
+    let timestamp_val = cx.number(timestamp);
+    value.set(cx, "timestamp", timestamp_val)?;
+    let hash = cx.string("sha256");
+    value.set(cx, "hash", hash)?;
+    value.set(cx, "content", content)?;
+
+    let unsigned = legacy_json::stringify_unsigned(cx, value)?;
+    let signature = sig::sign_bytes(&self.keypair, self.hmac_key.as_ref(), unsigned.as_bytes());
+    let json = legacy_json::with_signature(&unsigned, &signature);
+    if let Err(err) = validate::check_length(&json) {
+      return err.arg(content_index).throw(cx);
+    }
+    let signature = cx.string(signature);
+    value.set(cx, "signature", signature)?;
+
+    let state = FeedState {
+      author: self.author.clone(),
+      id: hash::msg_id(&json),
+      sequence,
+      timestamp,
+    };
+    let obj = cx.empty_object();
+    let key = cx.string(&state.id);
+    obj.set(cx, "key", key)?;
+    obj.set(cx, "value", value)?;
+    let state_obj = validate::state_to_js(cx, &state)?;
+    obj.set(cx, "state", state_obj)?;
+    self.state = Some(state);
+    Ok(obj)
+  }
+}
+
+fn feed_args(cx: &mut FunctionContext, name: &str) -> NeonResult<Feed> {
+  if cx.len() < 4 {
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!(
+        "{} requires at least four arguments: (keys, hmacKey, state, content)",
+        name
+      ),
+    );
+  }
+
+  let keypair = utils::keypair_arg(
+    cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
+  let author = keypair.public.as_base64().wrap('@', ".ed25519");
+
+  let hmac_key = if cx.argument::<JsValue>(1)?.is_truthy(cx) {
+    Some(hmac_key_arg(cx, 1, "2nd")?)
+  } else {
+    None
+  };
+
+  let state = cx.argument::<JsValue>(2)?;
+  let state = validate::state_value(cx, state, 2)?;
+  if state.as_ref().is_some_and(|state| state.author != author) {
+    return CodedError::new(
+      errors::INVALID_ARG_VALUE,
+      "expected the feed state to be of the feed of the keys",
+    )
+    .arg(2)
+    .throw(cx);
+  }
+
+  Ok(Feed {
+    keypair,
+    hmac_key,
+    author,
+    state,
+  })
+}
+
+// None for null and undefined, which mean the current time
+fn timestamp_value<'a>(
+  cx: &mut FunctionContext<'a>,
+  v: Handle<'a, JsValue>,
+  index: i32,
+) -> NeonResult<Option<f64>> {
+  if v.is_a::<JsNull>() || v.is_a::<JsUndefined>() {
+    return Ok(None);
+  }
+  match v.try_downcast::<JsNumber>().map(|n| n.value()) {
+    Some(timestamp) if timestamp.is_finite() => Ok(Some(timestamp)),
+    _ => CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected the timestamp to be a number",
+    )
+    .arg(index)
+    .throw(cx),
+  }
+}
+
+// createMessage: (keys: obj | string, hmac_key: Buffer | string | null, state: obj | null, content: obj | string, timestamp?: number) => obj
+pub fn neon_create_message(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let mut feed = feed_args(&mut cx, "createMessage")?;
+  let content = cx.argument::<JsValue>(3)?;
+  let timestamp = cx
+    .argument_opt(4)
+    .unwrap_or_else(|| cx.undefined().upcast());
+  let timestamp = timestamp_value(&mut cx, timestamp, 4)?;
+  feed.append(&mut cx, content, timestamp, 3, 4)
+}
+
+// createMessageBatch: (keys: obj | string, hmac_key: Buffer | string | null, state: obj | null, contents: Array<obj | string>, timestamps?: Array<number>) => Array<obj>
+pub fn neon_create_message_batch(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let mut feed = feed_args(&mut cx, "createMessageBatch")?;
+  let contents = cx
+    .arg_as::<JsArray>(
+      3,
+      "expected 4th argument to be an array of message contents",
+    )?
+    .to_vec(&mut cx)?;
+  let timestamps = match cx.argument_opt(4) {
+    Some(v) if !(v.is_a::<JsNull>() || v.is_a::<JsUndefined>()) => {
+      let timestamps = cx
+        .arg_as::<JsArray>(4, "expected 5th argument to be an array of timestamps")?
+        .to_vec(&mut cx)?;
+      if timestamps.len() != contents.len() {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          "expected as many timestamps as message contents",
+        )
+        .arg(4)
+        .throw(&mut cx);
+      }
+      timestamps
+        .into_iter()
+        .map(|timestamp| timestamp_value(&mut cx, timestamp, 4))
+        .collect::<NeonResult<Vec<_>>>()?
+    }
+    _ => vec![None; contents.len()],
+  };
+
+  let msgs = JsArray::new(&mut cx, contents.len() as u32);
+  for (i, (content, timestamp)) in contents.into_iter().zip(timestamps).enumerate() {
+    let msg = feed.append(&mut cx, content, timestamp, 3, 4)?;
+    msgs.set(&mut cx, i as u32, msg)?;
+  }
+  Ok(msgs)
+}
@@ -0,0 +1,115 @@
+use super::errors::{self, throw_code, CodedError};
+use super::utils::{self, make_keys_obj, ContextExt, HandleExt};
+use hmac::{Hmac, Mac, NewMac};
+use neon::prelude::*;
+use sha2::Sha512;
+use ssb_crypto::Keypair;
+use zeroize::Zeroizing;
+
+const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+// SLIP-0010 only defines hardened child derivation for ed25519, so every
+// segment of the path must be hardened, e.g. "m/44'/1'/0'"
+fn parse_path(path: &str) -> Result<Vec<u32>, String> {
+  let mut segments = path.split('/');
+  if segments.next() != Some("m") {
+    return Err(format!(
+      "derivation path must start with \"m\", got \"{}\"",
+      path
+    ));
+  }
+  segments
+    .map(|segment| {
+      let index = segment
+        .strip_suffix('\'')
+        .or_else(|| segment.strip_suffix('h'))
+        .ok_or_else(|| {
+          format!(
+            "derivation path segment \"{}\" must be hardened, ed25519 only supports hardened keys",
+            segment
+          )
+        })?;
+      match index.parse::<u32>() {
+        Ok(i) if i < HARDENED_OFFSET => Ok(i + HARDENED_OFFSET),
+        _ => Err(format!(
+          "derivation path segment \"{}\" is not a valid index",
+          segment
+        )),
+      }
+    })
+    .collect()
+}
+
+fn hmac_sha512(key: &[u8], chunks: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+  let mut mac = Hmac::<Sha512>::new_varkey(key).unwrap(); // HMAC accepts keys of any length
+  for chunk in chunks {
+    mac.update(chunk);
+  }
+  let mut out = Zeroizing::new([0u8; 64]);
+  out.copy_from_slice(&mac.finalize().into_bytes());
+  out
+}
+
+// Returns the 32-byte ed25519 seed at `path`, starting from `seed` as the
+// SLIP-0010 master seed
+fn derive_seed(seed: &[u8], path: &[u32]) -> Zeroizing<[u8; 32]> {
+  let i = hmac_sha512(b"ed25519 seed", &[seed]);
+  let (mut key, mut chain_code) = (Zeroizing::new([0u8; 32]), Zeroizing::new([0u8; 32]));
+  key.copy_from_slice(&i[..32]);
+  chain_code.copy_from_slice(&i[32..]);
+
+  for index in path {
+    let i = hmac_sha512(&chain_code[..], &[&[0u8], &key[..], &index.to_be_bytes()]);
+    key.copy_from_slice(&i[..32]);
+    chain_code.copy_from_slice(&i[32..]);
+  }
+  key
+}
+
+// deriveKeys: (rootKeys: obj | string | Buffer, path: string) => obj
+pub fn neon_derive_keys(mut cx: FunctionContext) -> JsResult<JsObject> {
+  // A buffer is the master seed itself, of 16 to 64 bytes like SLIP-0010 says
+  let root = cx.argument::<JsValue>(0)?;
+  let seed = match root.try_downcast::<JsBuffer>() {
+    Some(buf) => {
+      let seed = cx.borrow(&buf, |data| Zeroizing::new(data.as_slice::<u8>().to_vec()));
+      if !(16..=64).contains(&seed.len()) {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          "master seed must be 16 to 64 bytes",
+        )
+        .arg(0)
+        .throw(&mut cx);
+      }
+      seed
+    }
+    None => {
+      let root = utils::keypair_arg(
+        &mut cx,
+        0,
+        "expected 1st argument to be the keys object, the private key string or a seed buffer",
+        "cannot decode private key bytes",
+      )?;
+      Zeroizing::new(root.secret.0.to_vec())
+    }
+  };
+
+  let path = {
+    let path_str = cx
+      .arg_as::<JsString>(1, "expected 2nd argument to be a derivation path string")?
+      .value();
+    parse_path(&path_str).or_else(|e| {
+      CodedError::range_error(errors::INVALID_ARG_VALUE, e)
+        .arg(1)
+        .throw(&mut cx)
+    })?
+  };
+
+  let seed = derive_seed(&seed, &path);
+  let keypair = match Keypair::from_seed(&seed[..]) {
+    Some(keypair) => keypair,
+    None => return throw_code(&mut cx, errors::INTERNAL, "failed to derive keypair"),
+  };
+
+  make_keys_obj(&mut cx, &keypair)
+}
@@ -0,0 +1,438 @@
+use super::atomic::{replace_keyfile, write_keyfile};
+use super::errors::{self, CodedError};
+use super::load_create::{keyfile_path, write_error_code};
+use super::utils::{make_keys_obj, ContextExt, StringExt};
+use super::wipe::SecretString;
+use argon2::{Algorithm, Argon2, Params, Version};
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use ssb_crypto::{AsBytes, Keypair};
+use zeroize::Zeroizing;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The first line of an encrypted keyfile is this, followed by the format version
+pub const MAGIC: &str = "SSB-ENCRYPTED-KEYFILE";
+const VERSION: &str = "v1";
+
+// argon2id costs for new keyfiles: 64 MiB of memory, 3 passes, 1 lane.
+// They are stored in the file, so they can be raised later without breaking old files
+const KDF_NAME: &str = "argon2id";
+const KDF_M_COST: u32 = 64 * 1024;
+const KDF_T_COST: u32 = 3;
+const KDF_P_COST: u32 = 1;
+// The most a keyfile may ask for: 1 GiB of memory, 10 passes, 16 lanes.
+// Anything above is taken as a corrupt or hostile file, not spent on
+const KDF_MAX_M_COST: u32 = 1024 * 1024;
+const KDF_MAX_T_COST: u32 = 10;
+const KDF_MAX_P_COST: u32 = 16;
+const SALT_SIZE: usize = 16;
+
+const PRE_COMMENT: &str = "# WARNING: Never show this to anyone.
+# WARNING: Never edit it or use it on multiple devices at once.
+#
+# This is your SECRET, encrypted with a passphrase. It can only be opened
+# with `loadEncrypted` and the same passphrase.
+#
+";
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+  name: String,
+  m: u32,
+  t: u32,
+  p: u32,
+  salt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedKeyFile {
+  id: String,
+  kdf: KdfParams,
+  nonce: String,
+  ciphertext: String,
+}
+
+pub enum EncryptedError {
+  Io(io::Error),
+  Write(io::Error),
+  NotEncrypted,
+  UnsupportedVersion(String),
+  Malformed(String),
+  WrongPassphrase,
+}
+
+impl From<io::Error> for EncryptedError {
+  fn from(e: io::Error) -> Self {
+    EncryptedError::Io(e)
+  }
+}
+
+impl fmt::Display for EncryptedError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EncryptedError::Io(e) | EncryptedError::Write(e) => write!(f, "{}", e),
+      EncryptedError::NotEncrypted => write!(f, "keyfile is not encrypted, use `load` instead"),
+      EncryptedError::UnsupportedVersion(v) => {
+        write!(f, "unsupported encrypted keyfile version: {}", v)
+      }
+      EncryptedError::Malformed(reason) => write!(f, "encrypted keyfile is malformed: {}", reason),
+      EncryptedError::WrongPassphrase => {
+        write!(f, "wrong passphrase, or the encrypted keyfile is corrupted")
+      }
+    }
+  }
+}
+
+impl EncryptedError {
+  fn code(&self) -> &'static str {
+    match self {
+      EncryptedError::Io(e) if e.kind() == io::ErrorKind::NotFound => errors::KEYFILE_NOT_FOUND,
+      EncryptedError::Io(_) => errors::KEYFILE_READ,
+      EncryptedError::Write(e) => write_error_code(e),
+      EncryptedError::NotEncrypted => errors::KEYFILE_NOT_ENCRYPTED,
+      EncryptedError::UnsupportedVersion(_) | EncryptedError::Malformed(_) => {
+        errors::KEYFILE_CORRUPT
+      }
+      EncryptedError::WrongPassphrase => errors::DECRYPT_FAILED,
+    }
+  }
+}
+
+fn encrypted_error(e: &EncryptedError, path: &str) -> CodedError {
+  CodedError::new(e.code(), e.to_string()).field("path", path)
+}
+
+pub fn is_encrypted(contents: &str) -> bool {
+  contents.starts_with(MAGIC)
+}
+
+fn derive_key(passphrase: &str, kdf: &KdfParams) -> Result<Key, EncryptedError> {
+  if kdf.name != KDF_NAME {
+    return Err(EncryptedError::Malformed(format!(
+      "unknown kdf {}",
+      kdf.name
+    )));
+  }
+  if kdf.m > KDF_MAX_M_COST || kdf.t > KDF_MAX_T_COST || kdf.p > KDF_MAX_P_COST {
+    return Err(EncryptedError::Malformed(format!(
+      "kdf costs m={}, t={}, p={} are above the maximum of m={}, t={}, p={}",
+      kdf.m, kdf.t, kdf.p, KDF_MAX_M_COST, KDF_MAX_T_COST, KDF_MAX_P_COST
+    )));
+  }
+  let salt = base64::decode_config(&kdf.salt, base64::STANDARD)
+    .map_err(|_| EncryptedError::Malformed("cannot decode the kdf salt".to_string()))?;
+  let params = Params::new(kdf.m, kdf.t, kdf.p, Some(Key::SIZE))
+    .map_err(|e| EncryptedError::Malformed(format!("bad kdf params, {}", e)))?;
+
+  let mut key = Key([0u8; Key::SIZE]);
+  Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    .hash_password_into(passphrase.as_bytes(), &salt, &mut key.0)
+    .map_err(|e| EncryptedError::Malformed(format!("kdf failed, {}", e)))?;
+  Ok(key)
+}
+
+fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<String, EncryptedError> {
+  let mut salt = [0u8; SALT_SIZE];
+  getrandom::getrandom(&mut salt).map_err(io::Error::from)?;
+  let kdf = KdfParams {
+    name: KDF_NAME.to_string(),
+    m: KDF_M_COST,
+    t: KDF_T_COST,
+    p: KDF_P_COST,
+    salt: base64::encode_config(salt, base64::STANDARD),
+  };
+
+  let key = derive_key(passphrase, &kdf)?;
+  let nonce = Nonce::generate();
+  let mut ciphertext = vec![0u8; Hmac::SIZE + Keypair::SIZE];
+  key.seal_attached_into(keypair.as_bytes(), &nonce, &mut ciphertext);
+
+  let file = EncryptedKeyFile {
+    id: keypair.public.as_base64().wrap('@', ".ed25519"),
+    kdf,
+    nonce: base64::encode_config(nonce.0, base64::STANDARD),
+    ciphertext: base64::encode_config(&ciphertext, base64::STANDARD),
+  };
+  let json = serde_json::to_string_pretty(&file).unwrap(); // infallible
+  Ok(format!("{} {}\n{}{}\n", MAGIC, VERSION, PRE_COMMENT, json))
+}
+
+fn decrypt(contents: &str, passphrase: &str) -> Result<Keypair, EncryptedError> {
+  let mut lines = contents.lines();
+  let header = lines.next().unwrap_or_default();
+  if !is_encrypted(header) {
+    return Err(EncryptedError::NotEncrypted);
+  }
+  let version = header[MAGIC.len()..].trim();
+  if version != VERSION {
+    return Err(EncryptedError::UnsupportedVersion(version.to_string()));
+  }
+
+  let json = lines
+    .filter(|line| !line.starts_with('#'))
+    .collect::<String>();
+  let file = serde_json::from_str::<EncryptedKeyFile>(&json)
+    .map_err(|e| EncryptedError::Malformed(e.to_string()))?;
+
+  let key = derive_key(passphrase, &file.kdf)?;
+  let nonce = base64::decode_config(&file.nonce, base64::STANDARD)
+    .ok()
+    .and_then(|bytes| Nonce::from_slice(&bytes))
+    .ok_or_else(|| EncryptedError::Malformed("cannot decode the nonce".to_string()))?;
+  let ciphertext = base64::decode_config(&file.ciphertext, base64::STANDARD)
+    .ok()
+    .filter(|bytes| bytes.len() == Hmac::SIZE + Keypair::SIZE)
+    .ok_or_else(|| EncryptedError::Malformed("cannot decode the ciphertext".to_string()))?;
+
+  let mut plaintext = Zeroizing::new([0u8; Keypair::SIZE]);
+  if !key.open_attached_into(&ciphertext, &nonce, &mut plaintext[..]) {
+    return Err(EncryptedError::WrongPassphrase);
+  }
+  let keypair = Keypair::from_slice(&plaintext[..])
+    .ok_or_else(|| EncryptedError::Malformed("cannot decode the keypair".to_string()))?;
+  // The id is readable without the passphrase, so it must not claim another key
+  if keypair.public.as_base64().wrap('@', ".ed25519") != file.id {
+    return Err(EncryptedError::Malformed(format!(
+      "id {} is not that of the encrypted key",
+      file.id
+    )));
+  }
+  Ok(keypair)
+}
+
+fn internal_create_encrypted<P: AsRef<Path>>(
+  path: P,
+  passphrase: &str,
+) -> Result<Keypair, EncryptedError> {
+  let path = keyfile_path(path);
+  let keypair = Keypair::generate();
+  let contents = encrypt(&keypair, passphrase)?;
+
+  write_keyfile(&path, contents.as_bytes()).map_err(EncryptedError::Write)?;
+  Ok(keypair)
+}
+
+fn internal_load_encrypted<P: AsRef<Path>>(
+  path: P,
+  passphrase: &str,
+) -> Result<Keypair, EncryptedError> {
+  let contents = fs::read_to_string(keyfile_path(path))?;
+  decrypt(&contents, passphrase)
+}
+
+fn internal_change_passphrase<P: AsRef<Path>>(
+  path: P,
+  old_passphrase: &str,
+  new_passphrase: &str,
+) -> Result<Keypair, EncryptedError> {
+  let path = keyfile_path(path);
+  let keypair = decrypt(&fs::read_to_string(&path)?, old_passphrase)?;
+  let contents = encrypt(&keypair, new_passphrase)?;
+  replace_keyfile(&path, contents.as_bytes()).map_err(EncryptedError::Write)?;
+  Ok(keypair)
+}
+
+struct CreateEncryptedTask {
+  path: String,
+  passphrase: SecretString,
+}
+
+impl Task for CreateEncryptedTask {
+  type Output = Keypair;
+  type Error = EncryptedError;
+  type JsEvent = JsObject;
+
+  fn perform(&self) -> Result<Keypair, EncryptedError> {
+    internal_create_encrypted(&self.path, &self.passphrase)
+  }
+
+  fn complete(
+    self,
+    mut cx: TaskContext,
+    result: Result<Keypair, EncryptedError>,
+  ) -> JsResult<JsObject> {
+    let keypair = result.or_else(|e| encrypted_error(&e, &self.path).throw(&mut cx))?;
+
+    make_keys_obj(&mut cx, &keypair)
+  }
+}
+
+struct LoadEncryptedTask {
+  path: String,
+  passphrase: SecretString,
+}
+
+impl Task for LoadEncryptedTask {
+  type Output = Keypair;
+  type Error = EncryptedError;
+  type JsEvent = JsObject;
+
+  fn perform(&self) -> Result<Keypair, EncryptedError> {
+    internal_load_encrypted(&self.path, &self.passphrase)
+  }
+
+  fn complete(
+    self,
+    mut cx: TaskContext,
+    result: Result<Keypair, EncryptedError>,
+  ) -> JsResult<JsObject> {
+    let keypair = result.or_else(|e| encrypted_error(&e, &self.path).throw(&mut cx))?;
+
+    make_keys_obj(&mut cx, &keypair)
+  }
+}
+
+struct ChangePassphraseTask {
+  path: String,
+  old_passphrase: SecretString,
+  new_passphrase: SecretString,
+}
+
+impl Task for ChangePassphraseTask {
+  type Output = Keypair;
+  type Error = EncryptedError;
+  type JsEvent = JsObject;
+
+  fn perform(&self) -> Result<Keypair, EncryptedError> {
+    internal_change_passphrase(&self.path, &self.old_passphrase, &self.new_passphrase)
+  }
+
+  fn complete(
+    self,
+    mut cx: TaskContext,
+    result: Result<Keypair, EncryptedError>,
+  ) -> JsResult<JsObject> {
+    let keypair = result.or_else(|e| encrypted_error(&e, &self.path).throw(&mut cx))?;
+
+    make_keys_obj(&mut cx, &keypair)
+  }
+}
+
+fn passphrase_arg(cx: &mut FunctionContext, index: i32, fn_name: &str) -> NeonResult<SecretString> {
+  let passphrase = Zeroizing::new(
+    cx.arg_as::<JsString>(
+      index,
+      &format!("expected a passphrase string given to `{}`", fn_name),
+    )?
+    .value(),
+  );
+  if passphrase.is_empty() {
+    return CodedError::range_error(
+      errors::INVALID_ARG_VALUE,
+      format!("passphrase given to `{}` cannot be empty", fn_name),
+    )
+    .arg(index)
+    .throw(cx);
+  }
+  Ok(passphrase)
+}
+
+// createEncrypted: (path: string, passphrase: string, cb: fn) => void
+pub fn neon_create_encrypted(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `createEncrypted`",
+    )?
+    .value();
+  let passphrase = passphrase_arg(&mut cx, 1, "createEncrypted")?;
+  let cb = cx.arg_as::<JsFunction>(2, "expected a callback function given to `createEncrypted`")?;
+
+  let task = CreateEncryptedTask { path, passphrase };
+  task.schedule(cb);
+  Ok(cx.undefined())
+}
+
+// createEncryptedSync: (path: string, passphrase: string) => obj
+pub fn neon_create_encrypted_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `createEncryptedSync`",
+    )?
+    .value();
+  let passphrase = passphrase_arg(&mut cx, 1, "createEncryptedSync")?;
+
+  let keypair = internal_create_encrypted(&path, &passphrase)
+    .or_else(|e| encrypted_error(&e, &path).throw(&mut cx))?;
+
+  make_keys_obj(&mut cx, &keypair)
+}
+
+// loadEncrypted: (path: string, passphrase: string, cb: fn) => void
+pub fn neon_load_encrypted(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `loadEncrypted`",
+    )?
+    .value();
+  let passphrase = passphrase_arg(&mut cx, 1, "loadEncrypted")?;
+  let cb = cx.arg_as::<JsFunction>(2, "expected a callback function given to `loadEncrypted`")?;
+
+  let task = LoadEncryptedTask { path, passphrase };
+  task.schedule(cb);
+  Ok(cx.undefined())
+}
+
+// loadEncryptedSync: (path: string, passphrase: string) => obj
+pub fn neon_load_encrypted_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `loadEncryptedSync`",
+    )?
+    .value();
+  let passphrase = passphrase_arg(&mut cx, 1, "loadEncryptedSync")?;
+
+  let keypair = internal_load_encrypted(&path, &passphrase)
+    .or_else(|e| encrypted_error(&e, &path).throw(&mut cx))?;
+
+  make_keys_obj(&mut cx, &keypair)
+}
+
+// changePassphrase: (path: string, oldPassphrase: string, newPassphrase: string, cb: fn) => void
+pub fn neon_change_passphrase(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `changePassphrase`",
+    )?
+    .value();
+  let old_passphrase = passphrase_arg(&mut cx, 1, "changePassphrase")?;
+  let new_passphrase = passphrase_arg(&mut cx, 2, "changePassphrase")?;
+  let cb = cx.arg_as::<JsFunction>(
+    3,
+    "expected a callback function given to `changePassphrase`",
+  )?;
+
+  let task = ChangePassphraseTask {
+    path,
+    old_passphrase,
+    new_passphrase,
+  };
+  task.schedule(cb);
+  Ok(cx.undefined())
+}
+
+// changePassphraseSync: (path: string, oldPassphrase: string, newPassphrase: string) => obj
+pub fn neon_change_passphrase_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `changePassphraseSync`",
+    )?
+    .value();
+  let old_passphrase = passphrase_arg(&mut cx, 1, "changePassphraseSync")?;
+  let new_passphrase = passphrase_arg(&mut cx, 2, "changePassphraseSync")?;
+
+  let keypair = internal_change_passphrase(&path, &old_passphrase, &new_passphrase)
+    .or_else(|e| encrypted_error(&e, &path).throw(&mut cx))?;
+
+  make_keys_obj(&mut cx, &keypair)
+}
@@ -0,0 +1,131 @@
+use neon::prelude::*;
+
+// Stable values of the `code` property on thrown errors. Messages may change
+// between versions, these should not
+pub const INVALID_ARG_TYPE: &str = "ERR_INVALID_ARG_TYPE";
+pub const INVALID_ARG_VALUE: &str = "ERR_INVALID_ARG_VALUE";
+pub const MISSING_ARGS: &str = "ERR_MISSING_ARGS";
+pub const BAD_PRIVATE_KEY: &str = "ERR_SSB_KEYS_BAD_PRIVATE_KEY";
+pub const BAD_PUBLIC_KEY: &str = "ERR_SSB_KEYS_BAD_PUBLIC_KEY";
+pub const BAD_HMAC_KEY: &str = "ERR_SSB_KEYS_BAD_HMAC_KEY";
+pub const BAD_SIGNATURE: &str = "ERR_SSB_KEYS_BAD_SIGNATURE";
+pub const SIG_SUFFIX: &str = "ERR_SIG_SUFFIX";
+pub const BAD_CURVE: &str = "ERR_SSB_KEYS_BAD_CURVE";
+pub const BAD_SEED: &str = "ERR_SSB_KEYS_BAD_SEED";
+pub const WEAK_SEED: &str = "ERR_SSB_KEYS_WEAK_SEED";
+pub const BAD_MNEMONIC: &str = "ERR_SSB_KEYS_BAD_MNEMONIC";
+pub const BAD_ENCODING: &str = "ERR_SSB_KEYS_BAD_ENCODING";
+pub const BAD_SECRETBOX_KEY: &str = "ERR_SSB_KEYS_BAD_SECRETBOX_KEY";
+pub const DECRYPT_FAILED: &str = "ERR_SSB_KEYS_DECRYPT_FAILED";
+pub const INVALID_MESSAGE: &str = "ERR_SSB_KEYS_INVALID_MESSAGE";
+pub const INVALID_SIGNATURE: &str = "ERR_SSB_KEYS_INVALID_SIGNATURE";
+pub const OUT_OF_ORDER: &str = "ERR_SSB_KEYS_OUT_OF_ORDER";
+pub const FORK: &str = "ERR_SSB_KEYS_FORK";
+pub const VANITY_CANCELLED: &str = "ERR_SSB_KEYS_VANITY_CANCELLED";
+pub const VANITY_EXHAUSTED: &str = "ERR_SSB_KEYS_VANITY_EXHAUSTED";
+pub const INTERNAL: &str = "ERR_SSB_KEYS_INTERNAL";
+pub const KEYFILE_NOT_FOUND: &str = "ERR_KEYFILE_NOT_FOUND";
+pub const KEYFILE_READ: &str = "ERR_KEYFILE_READ";
+pub const KEYFILE_CORRUPT: &str = "ERR_KEYFILE_CORRUPT";
+pub const KEYFILE_ENCRYPTED: &str = "ERR_KEYFILE_ENCRYPTED";
+pub const KEYFILE_NOT_ENCRYPTED: &str = "ERR_KEYFILE_NOT_ENCRYPTED";
+pub const KEYFILE_INSECURE: &str = "ERR_KEYFILE_INSECURE";
+pub const KEYFILE_EXISTS: &str = "ERR_KEYFILE_EXISTS";
+pub const KEYFILE_WRITE: &str = "ERR_KEYFILE_WRITE";
+
+enum Class {
+  Error,
+  TypeError,
+  RangeError,
+}
+
+// An Error with a `code`, and optionally `argIndex` and other string fields,
+// to be thrown into JS
+pub struct CodedError {
+  class: Class,
+  code: &'static str,
+  message: String,
+  arg_index: Option<i32>,
+  fields: Vec<(&'static str, String)>,
+}
+
+impl CodedError {
+  fn with_class<S: Into<String>>(class: Class, code: &'static str, message: S) -> Self {
+    CodedError {
+      class,
+      code,
+      message: message.into(),
+      arg_index: None,
+      fields: vec![],
+    }
+  }
+
+  pub fn new<S: Into<String>>(code: &'static str, message: S) -> Self {
+    Self::with_class(Class::Error, code, message)
+  }
+
+  pub fn type_error<S: Into<String>>(code: &'static str, message: S) -> Self {
+    Self::with_class(Class::TypeError, code, message)
+  }
+
+  pub fn range_error<S: Into<String>>(code: &'static str, message: S) -> Self {
+    Self::with_class(Class::RangeError, code, message)
+  }
+
+  pub fn arg(mut self, index: i32) -> Self {
+    self.arg_index = Some(index);
+    self
+  }
+
+  pub fn field<S: Into<String>>(mut self, name: &'static str, value: S) -> Self {
+    self.fields.push((name, value.into()));
+    self
+  }
+
+  pub fn throw<'a, C: Context<'a>, T>(self, cx: &mut C) -> NeonResult<T> {
+    let err = self.into_js(cx)?;
+    cx.throw(err)
+  }
+
+  // The JS error, for when it is passed on instead of thrown
+  pub fn into_js<'a, C: Context<'a>>(self, cx: &mut C) -> JsResult<'a, JsError> {
+    let err = match self.class {
+      Class::Error => JsError::error(cx, &self.message)?,
+      Class::TypeError => JsError::type_error(cx, &self.message)?,
+      Class::RangeError => JsError::range_error(cx, &self.message)?,
+    };
+    let code = cx.string(self.code);
+    err.set(cx, "code", code)?;
+    if let Some(index) = self.arg_index {
+      let index = cx.number(index);
+      err.set(cx, "argIndex", index)?;
+    }
+    for (name, value) in self.fields {
+      let value = cx.string(value);
+      err.set(cx, name, value)?;
+    }
+    Ok(err)
+  }
+}
+
+// Shorthand for `CodedError::new(code, msg).throw(cx)`
+pub fn throw_code<'a, C: Context<'a>, T, S: Into<String>>(
+  cx: &mut C,
+  code: &'static str,
+  msg: S,
+) -> NeonResult<T> {
+  CodedError::new(code, msg).throw(cx)
+}
+
+pub trait OptionCodeExt<T> {
+  fn or_throw_code<'a, C: Context<'a>>(self, cx: &mut C, err: CodedError) -> NeonResult<T>;
+}
+
+impl<T> OptionCodeExt<T> for Option<T> {
+  fn or_throw_code<'a, C: Context<'a>>(self, cx: &mut C, err: CodedError) -> NeonResult<T> {
+    match self {
+      Some(v) => Ok(v),
+      None => err.throw(cx),
+    }
+  }
+}
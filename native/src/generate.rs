@@ -1,7 +1,145 @@
-use super::utils::{make_keys_obj, HandleExt, OptionExt};
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::utils::{self, get_field_opt, make_keys_obj, type_name, ContextExt, HandleExt};
+use super::wipe::{self, SecretBytes};
+use bip39::{Error as MnemonicError, Language, Mnemonic};
 use neon::prelude::*;
 use ssb_crypto::Keypair;
+use zeroize::Zeroizing;
 
+use std::fmt::Write;
+
+const SEED_SIZE: usize = 32;
+// 24 words of at most 8 letters, with spaces between them
+const MNEMONIC_MAX_LEN: usize = 24 * 9;
+
+// All the keypairs and keys objects of `generateMany` are held at once, so
+// more keys than this have to be split into several calls
+const GENERATE_MANY_MAX: u32 = 100_000;
+
+type Seed = Zeroizing<[u8; SEED_SIZE]>;
+
+// Seeds with very few distinct bytes, or with a constant step between bytes
+// (e.g. all zeros, or 0x00 0x01 0x02 ...), are typos or test vectors, not randomness
+fn is_low_entropy(seed: &[u8; SEED_SIZE]) -> bool {
+  let mut values = Zeroizing::new(*seed);
+  values.sort_unstable();
+  let mut values = Zeroizing::new(values.to_vec());
+  values.dedup();
+
+  let mut steps = Zeroizing::new([0u8; SEED_SIZE - 1]);
+  for (step, w) in steps.iter_mut().zip(seed.windows(2)) {
+    *step = w[1].wrapping_sub(w[0]);
+  }
+  steps.sort_unstable();
+  let mut steps = Zeroizing::new(steps.to_vec());
+  steps.dedup();
+
+  values.len() < 8 || steps.len() < 4
+}
+
+fn decode_seed_str(s: &str) -> Option<SecretBytes> {
+  if s.len() == SEED_SIZE * 2 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+    let mut bytes = Zeroizing::new(Vec::with_capacity(SEED_SIZE));
+    for i in (0..s.len()).step_by(2) {
+      bytes.push(u8::from_str_radix(&s[i..i + 2], 16).ok()?);
+    }
+    Some(bytes)
+  } else {
+    wipe::decode_base64(s, base64::STANDARD).or_else(|| wipe::decode_base64(s, base64::URL_SAFE))
+  }
+}
+
+// Reads a seed given as a Buffer (or any other Uint8Array, which node also
+// considers a Buffer), an ArrayBuffer, a hex string or a base64 string.
+// `index` is the argument it came from, for the thrown errors
+fn seed_from_js<'a>(
+  cx: &mut FunctionContext<'a>,
+  v: Handle<'a, JsValue>,
+  index: i32,
+  what: &str,
+  allow_weak: bool,
+) -> NeonResult<Seed> {
+  let bytes = if let Some(buf) = v.try_downcast::<JsBuffer>() {
+    cx.borrow(&buf, |data| Zeroizing::new(data.as_slice::<u8>().to_vec()))
+  } else if let Some(buf) = v.try_downcast::<JsArrayBuffer>() {
+    cx.borrow(&buf, |data| Zeroizing::new(data.as_slice::<u8>().to_vec()))
+  } else if let Some(s) = v.try_downcast::<JsString>() {
+    match decode_seed_str(&Zeroizing::new(s.value())) {
+      Some(bytes) => bytes,
+      None => {
+        return CodedError::type_error(
+          errors::BAD_SEED,
+          format!("{} string must be hex or base64", what),
+        )
+        .arg(index)
+        .throw(cx)
+      }
+    }
+  } else {
+    return CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      format!(
+        "{} must be a buffer, a hex string or a base64 string, was a {}",
+        what,
+        type_name(&v)
+      ),
+    )
+    .arg(index)
+    .throw(cx);
+  };
+
+  if bytes.len() != SEED_SIZE {
+    return CodedError::range_error(
+      errors::BAD_SEED,
+      format!(
+        "{} must be {} bytes, but it has {} bytes",
+        what,
+        SEED_SIZE,
+        bytes.len()
+      ),
+    )
+    .arg(index)
+    .throw(cx);
+  }
+  let mut seed = Zeroizing::new([0u8; SEED_SIZE]);
+  seed.copy_from_slice(&bytes);
+
+  if !allow_weak && is_low_entropy(&seed) {
+    return CodedError::range_error(
+      errors::WEAK_SEED,
+      format!(
+        "{} looks low-entropy, pass `{{allowWeakSeed: true}}` if this is intended",
+        what
+      ),
+    )
+    .arg(index)
+    .throw(cx);
+  }
+  Ok(seed)
+}
+
+// Reads `allowWeakSeed` from the options object at `index`, if there is one
+fn allow_weak_seed(cx: &mut FunctionContext, index: i32) -> NeonResult<bool> {
+  match cx
+    .argument_opt(index)
+    .and_then(|v| v.try_downcast::<JsObject>())
+    .filter(|v| !v.is_a::<JsFunction>())
+  {
+    Some(opts) => Ok(
+      get_field_opt::<JsBoolean>(
+        cx,
+        opts,
+        index,
+        "allowWeakSeed",
+        "opts.allowWeakSeed must be a boolean",
+      )?
+      .is_some_and(|b| b.value()),
+    ),
+    None => Ok(false),
+  }
+}
+
+// generate: (curve?: string, seed?: Buffer | string, opts?: obj) => obj
 pub fn neon_generate(mut cx: FunctionContext) -> JsResult<JsObject> {
   let args_length = cx.len();
   if args_length == 0 {
@@ -10,38 +148,235 @@ pub fn neon_generate(mut cx: FunctionContext) -> JsResult<JsObject> {
   }
 
   // First argument: curve (default = "ed25519")
-  let curve = if let Some(s) = cx
-    .argument::<JsValue>(0)
-    .unwrap()
-    .try_downcast::<JsString>()
-  {
-    s.value()
-  } else {
-    "ed25519".to_string()
+  let curve = {
+    let v = cx.argument::<JsValue>(0)?;
+    if let Some(s) = v.try_downcast::<JsString>() {
+      s.value()
+    } else if v.is_a::<JsUndefined>() || v.is_a::<JsNull>() {
+      "ed25519".to_string()
+    } else {
+      return CodedError::type_error(
+        errors::INVALID_ARG_TYPE,
+        format!("curve argument must be a string, was a {}", type_name(&v)),
+      )
+      .arg(0)
+      .throw(&mut cx);
+    }
   };
 
   // The only valid curve types: ['ed25519']
   if curve != "ed25519" {
-    return cx.throw_error("curve argument only supports: ed25519");
+    return CodedError::new(errors::BAD_CURVE, "curve argument only supports: ed25519")
+      .arg(0)
+      .throw(&mut cx);
   }
 
   // Second argument: seed
   let maybe_seed = cx
     .argument_opt(1)
-    .map(|v| {
-      v.try_downcast::<JsBuffer>()
-        .or_throw(&mut cx, "seed argument must be a buffer")
-    })
-    .transpose()?;
+    .filter(|v| !v.is_a::<JsUndefined>() && !v.is_a::<JsNull>());
 
   // Use seed if given, else, generate from random
   let keypair = match maybe_seed {
-    Some(seed_buffer) => {
-      let seed_bytes = cx.borrow(&seed_buffer, |data| data.as_slice::<u8>());
-      Keypair::from_seed(&seed_bytes).or_throw(&mut cx, "seed buffer must be 32 bytes")
+    Some(v) => {
+      let allow_weak = allow_weak_seed(&mut cx, 2)?;
+      let seed = seed_from_js(&mut cx, v, 1, "seed argument", allow_weak)?;
+      Keypair::from_seed(&seed[..]).unwrap() // infallible
     }
-    None => Ok(Keypair::generate()),
-  }?;
+    None => Keypair::generate(),
+  };
+
+  make_keys_obj(&mut cx, &keypair)
+}
+
+enum GenerateManyInput {
+  Count(u32),
+  Seeds(Vec<Seed>),
+}
+
+struct GenerateManyTask {
+  input: GenerateManyInput,
+}
+
+impl Task for GenerateManyTask {
+  type Output = Vec<Keypair>;
+  type Error = ();
+  type JsEvent = JsArray;
+
+  fn perform(&self) -> Result<Vec<Keypair>, ()> {
+    match &self.input {
+      GenerateManyInput::Count(n) => Ok((0..*n).map(|_| Keypair::generate()).collect()),
+      GenerateManyInput::Seeds(seeds) => seeds
+        .iter()
+        .map(|seed| Keypair::from_seed(&seed[..]).ok_or(()))
+        .collect(),
+    }
+  }
+
+  fn complete(self, mut cx: TaskContext, result: Result<Vec<Keypair>, ()>) -> JsResult<JsArray> {
+    let keypairs = result.or_else(|_| {
+      throw_code(
+        &mut cx,
+        errors::INTERNAL,
+        "failed to generate keypairs from seeds",
+      )
+    })?;
+
+    let out = JsArray::new(&mut cx, keypairs.len() as u32);
+    for (i, keypair) in keypairs.iter().enumerate() {
+      let keys_obj = make_keys_obj(&mut cx, keypair)?;
+      out.set(&mut cx, i as u32, keys_obj)?;
+    }
+    Ok(out)
+  }
+}
+
+// generateMany: (n: number | seeds: Array<Buffer | string>, opts?: obj, cb: fn) => void
+pub fn neon_generate_many(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let arg = cx.argument::<JsValue>(0)?;
+  let input = if let Some(n) = arg.try_downcast::<JsNumber>() {
+    let n = n.value();
+    if n < 0.0 || n.fract() != 0.0 || n > GENERATE_MANY_MAX as f64 {
+      return CodedError::range_error(
+        errors::INVALID_ARG_VALUE,
+        format!(
+          "expected 1st argument to `generateMany` to be a count from 0 to {}",
+          GENERATE_MANY_MAX
+        ),
+      )
+      .arg(0)
+      .throw(&mut cx);
+    }
+    GenerateManyInput::Count(n as u32)
+  } else if let Some(arr) = arg.try_downcast::<JsArray>() {
+    if arr.len() > GENERATE_MANY_MAX {
+      return CodedError::range_error(
+        errors::INVALID_ARG_VALUE,
+        format!(
+          "expected at most {} seeds given to `generateMany`",
+          GENERATE_MANY_MAX
+        ),
+      )
+      .arg(0)
+      .throw(&mut cx);
+    }
+    let allow_weak = allow_weak_seed(&mut cx, 1)?;
+    let mut seeds = Vec::with_capacity(arr.len() as usize);
+    for (i, v) in arr.to_vec(&mut cx)?.into_iter().enumerate() {
+      let what = format!("seed #{} given to `generateMany`", i);
+      seeds.push(seed_from_js(&mut cx, v, 0, &what, allow_weak)?);
+    }
+    GenerateManyInput::Seeds(seeds)
+  } else {
+    return CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected 1st argument to `generateMany` to be a number or an array of seeds",
+    )
+    .arg(0)
+    .throw(&mut cx);
+  };
+
+  let cb = cx.arg_as::<JsFunction>(
+    cx.len() - 1,
+    "expected a callback function given to `generateMany`",
+  )?;
+  let task = GenerateManyTask { input };
+  task.schedule(cb);
+  Ok(cx.undefined())
+}
+
+// toMnemonic: (keys: obj | string) => string
+pub fn neon_to_mnemonic(mut cx: FunctionContext) -> JsResult<JsString> {
+  let keypair = utils::keypair_arg(
+    &mut cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
+
+  // Like ssb-keys-mnemonic: each 16-byte half of the 32-byte seed is its own
+  // 12-word mnemonic with a 4-bit checksum, and the two are joined
+  let mut words = Zeroizing::new(String::with_capacity(MNEMONIC_MAX_LEN));
+  for half in keypair.secret.0.chunks(16) {
+    let mnemonic = Mnemonic::from_entropy_in(Language::English, half)
+      .or_else(|e| throw_code(&mut cx, errors::INTERNAL, e.to_string()))?;
+    if !words.is_empty() {
+      words.push(' ');
+    }
+    write!(words, "{}", mnemonic).unwrap(); // infallible
+  }
+
+  Ok(cx.string(&*words))
+}
+
+// fromMnemonic: (words: string) => obj
+pub fn neon_from_mnemonic(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let input = Zeroizing::new(
+    cx.arg_as::<JsString>(
+      0,
+      "expected string of words as the 1st argument to `fromMnemonic`",
+    )?
+    .value(),
+  );
+  let words = Zeroizing::new(
+    input
+      .split_whitespace()
+      .map(|w| w.to_lowercase())
+      .collect::<Vec<String>>(),
+  );
+
+  if words.len() != 24 {
+    return CodedError::new(
+      errors::BAD_MNEMONIC,
+      format!("mnemonic must have 24 words, but it has {}", words.len()),
+    )
+    .arg(0)
+    .throw(&mut cx);
+  }
+
+  // Each half of the words is a mnemonic of one half of the seed
+  let mut seed = Zeroizing::new([0u8; 32]);
+  for (half, half_words) in words.chunks(12).enumerate() {
+    let normalized = Zeroizing::new(half_words.join(" "));
+    let mnemonic = match Mnemonic::parse_in_normalized(Language::English, &normalized) {
+      Ok(mnemonic) => mnemonic,
+      Err(MnemonicError::UnknownWord(i)) => {
+        return CodedError::new(
+          errors::BAD_MNEMONIC,
+          format!(
+            "mnemonic word #{} \"{}\" is not in the BIP39 english wordlist",
+            half * 12 + i + 1,
+            half_words[i]
+          ),
+        )
+        .arg(0)
+        .throw(&mut cx)
+      }
+      Err(MnemonicError::InvalidChecksum) => {
+        return CodedError::new(
+          errors::BAD_MNEMONIC,
+          format!(
+            "mnemonic has an invalid checksum in its {} half, some word may be misspelled",
+            if half == 0 { "first" } else { "second" }
+          ),
+        )
+        .arg(0)
+        .throw(&mut cx)
+      }
+      Err(e) => {
+        return CodedError::new(errors::BAD_MNEMONIC, e.to_string())
+          .arg(0)
+          .throw(&mut cx)
+      }
+    };
+    let entropy = Zeroizing::new(mnemonic.to_entropy_array().0);
+    seed[half * 16..(half + 1) * 16].copy_from_slice(&entropy[..16]);
+  }
+
+  let keypair = Keypair::from_seed(&seed[..]).or_throw_code(
+    &mut cx,
+    CodedError::new(errors::BAD_MNEMONIC, "mnemonic must encode 32 bytes").arg(0),
+  )?;
 
   make_keys_obj(&mut cx, &keypair)
 }
@@ -1,26 +1,38 @@
-use super::utils::{self, StringExt};
+use super::errors::{self, CodedError, OptionCodeExt};
+use super::legacy_json;
+use super::pool;
+use super::utils::{self, ContextExt, StringExt};
 use arrayvec::ArrayVec;
 use neon::prelude::*;
 use ssb_crypto::hash;
 
-pub fn neon_hash(mut cx: FunctionContext) -> JsResult<JsString> {
-  let args_length = cx.len();
-
+// The bytes to hash, from the first `argc` arguments
+fn hash_args(cx: &mut FunctionContext, argc: i32) -> NeonResult<Vec<u8>> {
   let data = cx.argument::<JsValue>(0)?;
   if !(data.is_a::<JsString>() || data.is_a::<JsBuffer>()) {
-    return cx.throw_error("expected 1st argument to `hash` to be a string or buffer");
+    return CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected 1st argument to `hash` to be a string or buffer",
+    )
+    .arg(0)
+    .throw(cx);
   }
 
   let enc = {
     let fallback = cx.string("binary").upcast::<JsValue>();
-    if args_length == 2 {
+    if argc == 2 {
       cx.argument::<JsValue>(1).and_then(|v| {
         if v.is_a::<JsString>() {
           Ok(v)
         } else if v.is_a::<JsNull>() || v.is_a::<JsUndefined>() {
           Ok(fallback)
         } else {
-          cx.throw_error("expected encoding string as the 2nd argument to `hash`")
+          CodedError::type_error(
+            errors::BAD_ENCODING,
+            "expected encoding string as the 2nd argument to `hash`",
+          )
+          .arg(1)
+          .throw(cx)
         }
       })
     } else {
@@ -28,8 +40,95 @@ pub fn neon_hash(mut cx: FunctionContext) -> JsResult<JsString> {
     }
   }?;
 
-  let data_buffer = utils::buffer_from(&mut cx, ArrayVec::from([data, enc]))?;
-  let data_bytes = cx.borrow(&data_buffer, |bytes| bytes.as_slice::<u8>());
+  let data_buffer = utils::buffer_from(cx, ArrayVec::from([data, enc]))?;
+  Ok(cx.borrow(&data_buffer, |bytes| bytes.as_slice::<u8>().to_vec()))
+}
+
+fn hash_to_string(data: &[u8]) -> String {
+  hash(data).as_base64().with_suffix(".sha256")
+}
 
-  Ok(cx.string(hash(data_bytes).as_base64().with_suffix(".sha256")))
+pub fn neon_hash(mut cx: FunctionContext) -> JsResult<JsString> {
+  let argc = cx.len();
+  let data = hash_args(&mut cx, argc)?;
+  Ok(cx.string(hash_to_string(&data)))
+}
+
+// hashAsync: (data: string | Buffer, enc?: string, cb: (err, string) => void) => void
+pub fn neon_hash_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "hashAsync")?;
+  let argc = cx.len() - 1;
+  let data = hash_args(&mut cx, argc)?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || hash_to_string(&data),
+    |cx, _, hash| Ok(cx.string(hash).upcast()),
+  );
+  Ok(cx.undefined())
+}
+
+// The id of a legacy message, hashing its JSON like ssb-validate does: with
+// `hash(json)`, which treats the string as latin1, so each UTF-16 code unit
+// is cut down to its low byte. Messages with non-ASCII content depend on it
+pub fn msg_id(json: &str) -> String {
+  let latin1 = json
+    .encode_utf16()
+    .map(|unit| unit as u8)
+    .collect::<Vec<_>>();
+  let mut id = String::with_capacity(52);
+  id.push('%');
+  id.push_str(&hash_to_string(&latin1));
+  id
+}
+
+// The id of a message value, which is or is in the argument at `index`
+fn msg_id_value<'a>(
+  cx: &mut FunctionContext<'a>,
+  value: Handle<'a, JsValue>,
+  index: i32,
+  expected_msg: &str,
+) -> NeonResult<String> {
+  let value = value.downcast::<JsObject>().ok().or_throw_code(
+    cx,
+    CodedError::type_error(errors::INVALID_ARG_TYPE, expected_msg).arg(index),
+  )?;
+  let json = legacy_json::stringify(cx, value.upcast())?.or_throw_code(
+    cx,
+    CodedError::type_error(errors::INVALID_ARG_TYPE, expected_msg).arg(index),
+  )?;
+  Ok(msg_id(&json))
+}
+
+// msgId: (value: obj) => string
+pub fn neon_msg_id(mut cx: FunctionContext) -> JsResult<JsString> {
+  let value = cx.argument::<JsValue>(0)?;
+  let id = msg_id_value(
+    &mut cx,
+    value,
+    0,
+    "expected 1st argument to be a message value object",
+  )?;
+  Ok(cx.string(id))
+}
+
+// msgIdBatch: (values: Array<obj>) => Array<string>
+pub fn neon_msg_id_batch(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let values = cx
+    .arg_as::<JsArray>(0, "expected 1st argument to be an array of message values")?
+    .to_vec(&mut cx)?;
+  let ids = JsArray::new(&mut cx, values.len() as u32);
+  for (i, value) in values.into_iter().enumerate() {
+    let id = msg_id_value(
+      &mut cx,
+      value,
+      0,
+      "expected 1st argument to be an array of message value objects",
+    )?;
+    let id = cx.string(id);
+    ids.set(&mut cx, i as u32, id)?;
+  }
+  Ok(ids)
 }
@@ -0,0 +1,86 @@
+use super::load_create::{internal_load, load_error};
+use super::secure_memory::Secret;
+use super::sig::{neon_sign, neon_sign_obj};
+use super::unbox::{neon_sk_to_curve, neon_unbox, neon_unbox_key};
+use super::utils::{ContextExt, StringExt};
+use neon::prelude::*;
+use ssb_crypto::Keypair;
+
+// The keypair behind an identity handle. It is only reachable from Rust, so
+// the secret key never becomes a JS string, and it lives in locked pages
+// when secure memory is enabled
+pub struct Identity {
+  pub keypair: Secret<Keypair>,
+}
+
+// Calls one of the module functions with the method arguments, and with the
+// handle itself as the keys argument at `keys_index`
+fn delegate<'a, U: Value>(
+  cx: &mut MethodContext<'a, JsIdentity>,
+  keys_index: usize,
+  f: fn(FunctionContext) -> JsResult<U>,
+) -> JsResult<'a, JsValue> {
+  let this = cx.this();
+  let mut args = (0..cx.len())
+    .filter_map(|i| cx.argument_opt(i))
+    .collect::<Vec<_>>();
+  while args.len() < keys_index {
+    args.push(cx.undefined().upcast());
+  }
+  args.insert(keys_index, this.upcast());
+  let func = JsFunction::new(cx, f)?;
+  let null = cx.null();
+  func.call(cx, null, args)
+}
+
+declare_types! {
+  pub class JsIdentity for Identity {
+    // Only meant to be called by `openIdentity`, with a keyfile path
+    init(mut cx) {
+      let path = cx.argument::<JsString>(0)?.value();
+      let keypair = internal_load(&path).or_else(|e| load_error(&e, &path).throw(&mut cx))?;
+      Ok(Identity { keypair: Secret::new(keypair) })
+    }
+
+    // sign: (hmac_key?: Buffer | string, str: string) => string
+    method sign(mut cx) {
+      delegate(&mut cx, 0, neon_sign)
+    }
+
+    // signObj: (hmac_key?: Buffer | string, obj: obj) => obj
+    method signObj(mut cx) {
+      delegate(&mut cx, 0, neon_sign_obj)
+    }
+
+    // unbox: (ciphertext: string) => any
+    method unbox(mut cx) {
+      delegate(&mut cx, 1, neon_unbox)
+    }
+
+    // unboxKey: (ciphertext: string) => Buffer | undefined
+    method unboxKey(mut cx) {
+      delegate(&mut cx, 1, neon_unbox_key)
+    }
+
+    // toCurve: () => Buffer, like `ssbSecretKeyToPrivateBoxSecret`
+    method toCurve(mut cx) {
+      delegate(&mut cx, 0, neon_sk_to_curve)
+    }
+  }
+}
+
+// openIdentity: (path: string) => handle with {curve, id, public} and the methods above
+pub fn neon_open_identity(mut cx: FunctionContext) -> JsResult<JsIdentity> {
+  let path = cx.arg_as::<JsString>(0, "expected string as the first argument to `openIdentity`")?;
+  let identity = JsIdentity::new(&mut cx, vec![path.upcast::<JsValue>()])?;
+
+  // Like a keys object without `private`, so `verify` and `box` accept it too
+  let public = cx.borrow(&identity, |identity| identity.keypair.public.as_base64());
+  let curve = cx.string("ed25519");
+  let id = cx.string(public.clone().wrap('@', ".ed25519"));
+  let public = cx.string(public.with_suffix(".ed25519"));
+  identity.set(&mut cx, "curve", curve)?;
+  identity.set(&mut cx, "id", id)?;
+  identity.set(&mut cx, "public", public)?;
+  Ok(identity)
+}
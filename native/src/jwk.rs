@@ -0,0 +1,149 @@
+use super::errors::{self, CodedError};
+use super::utils::{self, make_keys_obj, make_public_keys_obj, HandleExt};
+use super::wipe::{self, SecretBytes};
+use arrayvec::ArrayVec;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use ssb_crypto::{Keypair, PublicKey};
+use zeroize::{Zeroize, Zeroizing};
+
+use std::fmt;
+
+pub enum JwkError {
+  Json(String),
+  UnsupportedKty(String),
+  UnsupportedCrv(String),
+  BadField(&'static str),
+  KeyMismatch,
+}
+
+impl fmt::Display for JwkError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      JwkError::Json(e) => write!(f, "malformed JWK: {}", e),
+      JwkError::UnsupportedKty(kty) => write!(f, "JWK kty must be \"OKP\", but it is \"{}\"", kty),
+      JwkError::UnsupportedCrv(crv) => {
+        write!(f, "JWK crv must be \"Ed25519\", but it is \"{}\"", crv)
+      }
+      JwkError::BadField(field) => {
+        write!(f, "JWK `{}` must be 32 bytes encoded as base64url", field)
+      }
+      JwkError::KeyMismatch => write!(f, "JWK `x` does not match the private key `d`"),
+    }
+  }
+}
+
+impl JwkError {
+  // Errors about the JWK given as the first argument
+  fn coded(&self) -> CodedError {
+    let err = match self {
+      JwkError::Json(_) => CodedError::type_error(errors::BAD_ENCODING, self.to_string()),
+      JwkError::UnsupportedKty(_) | JwkError::UnsupportedCrv(_) => {
+        CodedError::range_error(errors::BAD_ENCODING, self.to_string())
+      }
+      JwkError::BadField("x") => CodedError::range_error(errors::BAD_PUBLIC_KEY, self.to_string()),
+      JwkError::BadField(_) | JwkError::KeyMismatch => {
+        CodedError::range_error(errors::BAD_PRIVATE_KEY, self.to_string())
+      }
+    };
+    err.arg(0)
+  }
+}
+
+// An OKP JSON Web Key, see RFC 8037
+#[derive(Serialize, Deserialize)]
+struct Jwk {
+  kty: String,
+  crv: String,
+  x: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  d: Option<String>,
+}
+
+impl Drop for Jwk {
+  fn drop(&mut self) {
+    self.d.zeroize();
+  }
+}
+
+enum JwkKey {
+  Private(Keypair),
+  Public(PublicKey),
+}
+
+fn encode(bytes: &[u8]) -> String {
+  base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_32(b64: &str, field: &'static str) -> Result<SecretBytes, JwkError> {
+  wipe::decode_base64(b64, base64::URL_SAFE_NO_PAD)
+    .filter(|bytes| bytes.len() == 32)
+    .ok_or(JwkError::BadField(field))
+}
+
+fn to_jwk(keypair: &Keypair) -> Jwk {
+  Jwk {
+    kty: "OKP".to_string(),
+    crv: "Ed25519".to_string(),
+    x: encode(&keypair.public.0),
+    d: Some(encode(&keypair.secret.0)),
+  }
+}
+
+fn from_jwk(json: &str) -> Result<JwkKey, JwkError> {
+  let jwk = serde_json::from_str::<Jwk>(json).map_err(|e| JwkError::Json(e.to_string()))?;
+  if jwk.kty != "OKP" {
+    return Err(JwkError::UnsupportedKty(jwk.kty.clone()));
+  }
+  if jwk.crv != "Ed25519" {
+    return Err(JwkError::UnsupportedCrv(jwk.crv.clone()));
+  }
+  let public = PublicKey::from_slice(&decode_32(&jwk.x, "x")?).unwrap(); // checked length
+  match &jwk.d {
+    None => Ok(JwkKey::Public(public)),
+    Some(d) => {
+      let keypair = Keypair::from_seed(&decode_32(d, "d")?).unwrap(); // checked length
+      if keypair.public != public {
+        return Err(JwkError::KeyMismatch);
+      }
+      Ok(JwkKey::Private(keypair))
+    }
+  }
+}
+
+// toJWK: (keys: obj | string) => {kty, crv, x, d}
+pub fn neon_to_jwk(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let keypair = utils::keypair_arg(
+    &mut cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
+
+  let json = Zeroizing::new(serde_json::to_string(&to_jwk(&keypair)).unwrap()); // infallible
+  let json = cx.string(&*json);
+  utils::json_parse(&mut cx, json)
+}
+
+// fromJWK: (jwk: obj | string) => obj, without `private` if the JWK has no `d`
+pub fn neon_from_jwk(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let arg = cx.argument::<JsValue>(0)?;
+  let json = Zeroizing::new(if let Some(s) = arg.try_downcast::<JsString>() {
+    s.value()
+  } else if arg.is_a::<JsObject>() {
+    utils::json_stringify(&mut cx, ArrayVec::from([arg]))?.value()
+  } else {
+    return CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected the JWK object as the only argument to `fromJWK`",
+    )
+    .arg(0)
+    .throw(&mut cx);
+  });
+
+  match from_jwk(&json) {
+    Ok(JwkKey::Private(keypair)) => make_keys_obj(&mut cx, &keypair),
+    Ok(JwkKey::Public(public)) => make_public_keys_obj(&mut cx, &public),
+    Err(e) => e.coded().throw(&mut cx),
+  }
+}
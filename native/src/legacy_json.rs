@@ -0,0 +1,383 @@
+use super::utils;
+use arrayvec::ArrayVec;
+use neon::prelude::*;
+
+use std::fmt::Write;
+
+// `JSON.stringify(value, null, 2)`, which is how legacy SSB messages are
+// serialized for signing and hashing, written in Rust so that the value is
+// walked once instead of going through JS. Values it can't write byte for
+// byte on its own (symbols, bigints, cycles, very deep nesting) are still
+// handed to JS, which may throw like it did before
+//
+// Boxed primitives like `new String("a")` are written as plain objects,
+// unlike in JS, but they never occur in parsed messages
+
+// Deeper than this, the rest is left to JS, which throws on very deep values
+const MAX_DEPTH: usize = 256;
+
+// The property name that `toJSON` is called with
+enum Key<'k> {
+  Name(Handle<'k, JsValue>),
+  Index(u32),
+}
+
+// What to do with the own properties of the top level object
+enum Top<'a> {
+  Plain,
+  // Also copy them into this object
+  CopyInto(Handle<'a, JsObject>),
+  // Leave out this one
+  Skip(&'static str),
+}
+
+struct Writer<'a> {
+  out: String,
+  // The objects being written, to detect cycles
+  stack: Vec<Handle<'a, JsObject>>,
+}
+
+impl<'a> Writer<'a> {
+  fn new() -> Self {
+    Writer {
+      out: String::new(),
+      stack: Vec::new(),
+    }
+  }
+
+  fn indent(&mut self, depth: usize) {
+    for _ in 0..depth {
+      self.out.push_str("  ");
+    }
+  }
+
+  // Returns false if nothing was written, where JSON.stringify would skip an
+  // object field or write `null` in an array
+  fn value<C: Context<'a>>(
+    &mut self,
+    cx: &mut C,
+    key: Key<'a>,
+    v: Handle<'a, JsValue>,
+    depth: usize,
+  ) -> NeonResult<bool> {
+    let obj = match v.downcast::<JsObject>() {
+      Ok(obj) if !v.is_a::<JsFunction>() => obj,
+      _ => return self.plain_value(cx, v, depth),
+    };
+    let to_json: Handle<JsValue> = obj.get(cx, "toJSON")?;
+    match to_json.downcast::<JsFunction>() {
+      Ok(to_json) => {
+        let key = match key {
+          Key::Name(name) => name,
+          Key::Index(i) => cx.string(i.to_string()).upcast(),
+        };
+        let v = to_json.call(cx, obj, ArrayVec::from([key]))?;
+        self.plain_value(cx, v, depth)
+      }
+      Err(_) => self.plain_value(cx, v, depth),
+    }
+  }
+
+  fn plain_value<C: Context<'a>>(
+    &mut self,
+    cx: &mut C,
+    v: Handle<'a, JsValue>,
+    depth: usize,
+  ) -> NeonResult<bool> {
+    if let Ok(s) = v.downcast::<JsString>() {
+      self.string(cx, s)?;
+    } else if let Ok(n) = v.downcast::<JsNumber>() {
+      write_number(&mut self.out, n.value());
+    } else if let Ok(b) = v.downcast::<JsBoolean>() {
+      self.out.push_str(if b.value() { "true" } else { "false" });
+    } else if v.is_a::<JsNull>() {
+      self.out.push_str("null");
+    } else if v.is_a::<JsUndefined>() || v.is_a::<JsFunction>() {
+      return Ok(false);
+    } else if let Ok(arr) = v.downcast::<JsArray>() {
+      return self.array(cx, arr, depth);
+    } else if let Ok(obj) = v.downcast::<JsObject>() {
+      return self.object(cx, obj, depth, Top::Plain);
+    } else {
+      return self.fallback(cx, v, depth);
+    }
+    Ok(true)
+  }
+
+  fn string<C: Context<'a>>(&mut self, cx: &mut C, s: Handle<'a, JsString>) -> NeonResult<()> {
+    let value = s.value();
+    // Lone surrogates were replaced when converting to UTF-8, so only JS
+    // knows how to escape them
+    if value.contains('\u{FFFD}') {
+      self.fallback(cx, s.upcast(), 0)?;
+    } else {
+      write_escaped(&mut self.out, &value);
+    }
+    Ok(())
+  }
+
+  // Objects and arrays already being written, or nested too deep
+  fn enter(&self, obj: Handle<'a, JsObject>) -> bool {
+    // Handles are equal when they are the same JS object
+    self.stack.len() < MAX_DEPTH && !self.stack.contains(&obj)
+  }
+
+  fn array<C: Context<'a>>(
+    &mut self,
+    cx: &mut C,
+    arr: Handle<'a, JsArray>,
+    depth: usize,
+  ) -> NeonResult<bool> {
+    if !self.enter(arr.upcast()) {
+      return self.fallback(cx, arr.upcast(), depth);
+    }
+    self.stack.push(arr.upcast());
+    let len = arr.len();
+    self.out.push('[');
+    for i in 0..len {
+      self.out.push_str(if i == 0 { "\n" } else { ",\n" });
+      self.indent(depth + 1);
+      let v: Handle<JsValue> = arr.get(cx, i)?;
+      if !self.value(cx, Key::Index(i), v, depth + 1)? {
+        self.out.push_str("null");
+      }
+    }
+    if len > 0 {
+      self.out.push('\n');
+      self.indent(depth);
+    }
+    self.out.push(']');
+    self.stack.pop();
+    Ok(true)
+  }
+
+  fn object<C: Context<'a>>(
+    &mut self,
+    cx: &mut C,
+    obj: Handle<'a, JsObject>,
+    depth: usize,
+    top: Top<'a>,
+  ) -> NeonResult<bool> {
+    if !self.enter(obj) {
+      return self.fallback(cx, obj.upcast(), depth);
+    }
+    self.stack.push(obj);
+    let keys = obj.get_own_property_names(cx)?;
+    let mut empty = true;
+    self.out.push('{');
+    for i in 0..keys.len() {
+      // Integer keys may come as numbers
+      let key: Handle<JsValue> = keys.get(cx, i)?;
+      let name = match key.downcast::<JsNumber>() {
+        Ok(n) => cx.string(n.value().to_string()),
+        Err(_) => key.downcast::<JsString>().or_throw(cx)?,
+      };
+      if let Top::Skip(skip) = top {
+        if name.value() == skip {
+          continue;
+        }
+      }
+      let v: Handle<JsValue> = obj.get(cx, name)?;
+      if let Top::CopyInto(copy) = top {
+        copy.set(cx, name, v)?;
+      }
+
+      let mark = self.out.len();
+      self.out.push_str(if empty { "\n" } else { ",\n" });
+      self.indent(depth + 1);
+      self.string(cx, name)?;
+      self.out.push_str(": ");
+      if self.value(cx, Key::Name(name.upcast()), v, depth + 1)? {
+        empty = false;
+      } else {
+        self.out.truncate(mark);
+      }
+    }
+    if !empty {
+      self.out.push('\n');
+      self.indent(depth);
+    }
+    self.out.push('}');
+    self.stack.pop();
+    Ok(true)
+  }
+
+  // Lets JS write the value, indented for where it is
+  fn fallback<C: Context<'a>>(
+    &mut self,
+    cx: &mut C,
+    v: Handle<'a, JsValue>,
+    depth: usize,
+  ) -> NeonResult<bool> {
+    let args = ArrayVec::from([v, cx.null().upcast(), cx.number(2).upcast()]);
+    let json: Handle<JsValue> = utils::call_builtin(cx, "JSON", "stringify", args)?;
+    let json = match json.downcast::<JsString>() {
+      Ok(json) => json.value(),
+      Err(_) => return Ok(false),
+    };
+    // Strings in JSON have no raw newlines, so every newline starts a line
+    for (i, line) in json.split('\n').enumerate() {
+      if i > 0 {
+        self.out.push('\n');
+        self.indent(depth);
+      }
+      self.out.push_str(line);
+    }
+    Ok(true)
+  }
+}
+
+// Like JSON.stringify does: `"`, `\` and control characters are escaped, and
+// everything else is written as is
+fn write_escaped(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\u{8}' => out.push_str("\\b"),
+      '\u{c}' => out.push_str("\\f"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+// Like Number.prototype.toString in JS: the shortest digits that round-trip,
+// in decimal notation for exponents from -7 to 20 and exponential otherwise
+fn write_number(out: &mut String, x: f64) {
+  if !x.is_finite() {
+    out.push_str("null");
+    return;
+  }
+  // Also -0
+  if x == 0.0 {
+    out.push('0');
+    return;
+  }
+  if x < 0.0 {
+    out.push('-');
+  }
+  let sci = format!("{:e}", x.abs());
+  let (mantissa, exp) = sci.split_once('e').unwrap();
+  let mut digits = mantissa.replace('.', "");
+  // Only doubles that need 16 or 17 digits can fall exactly halfway
+  if digits.len() >= 16 {
+    digits = to_even(x.abs(), digits, exp);
+  }
+  let k = digits.len() as i32;
+  let n = exp.parse::<i32>().unwrap() + 1;
+  if k <= n && n <= 21 {
+    out.push_str(&digits);
+    out.push_str(&"0".repeat((n - k) as usize));
+  } else if 0 < n && n <= 21 {
+    out.push_str(&digits[..n as usize]);
+    out.push('.');
+    out.push_str(&digits[n as usize..]);
+  } else if -6 < n && n <= 0 {
+    out.push_str("0.");
+    out.push_str(&"0".repeat(-n as usize));
+    out.push_str(&digits);
+  } else {
+    out.push_str(&digits[..1]);
+    if k > 1 {
+      out.push('.');
+      out.push_str(&digits[1..]);
+    }
+    out.push('e');
+    out.push(if n > 0 { '+' } else { '-' });
+    write!(out, "{}", (n - 1).abs()).unwrap();
+  }
+}
+
+// When the double lies exactly halfway between the two shortest decimals that
+// round-trip, Rust picks the upper one while JS picks the even one
+fn to_even(x: f64, digits: String, exp: &str) -> String {
+  // Every double has an exact decimal expansion of at most 767 digits
+  let exact = format!("{:.800e}", x);
+  let (mantissa, exact_exp) = exact.split_once('e').unwrap();
+  let exact_digits = mantissa.replace('.', "");
+  let exact_digits = exact_digits.trim_end_matches('0');
+  let k = digits.len();
+  if exact_exp != exp || exact_digits.len() != k + 1 || !exact_digits.ends_with('5') {
+    return digits;
+  }
+  let lower = &exact_digits[..k];
+  let candidate = if lower != digits {
+    lower.to_string()
+  } else {
+    let mut upper = lower.as_bytes().to_vec();
+    let mut i = k;
+    loop {
+      if i == 0 {
+        return digits;
+      }
+      i -= 1;
+      if upper[i] == b'9' {
+        upper[i] = b'0';
+      } else {
+        upper[i] += 1;
+        break;
+      }
+    }
+    String::from_utf8(upper).unwrap()
+  };
+  let even = |d: &str| matches!(d.as_bytes()[k - 1], b'0' | b'2' | b'4' | b'6' | b'8');
+  let parsed = format!("{}.{}e{}", &candidate[..1], &candidate[1..], exp).parse::<f64>();
+  if !even(&digits) && even(&candidate) && parsed == Ok(x) {
+    candidate
+  } else {
+    digits
+  }
+}
+
+// JSON.stringify(value, null, 2), or None where that returns undefined
+pub fn stringify<'a>(
+  cx: &mut impl Context<'a>,
+  value: Handle<'a, JsValue>,
+) -> NeonResult<Option<String>> {
+  let mut writer = Writer::new();
+  let key = cx.string("").upcast();
+  let written = writer.value(cx, Key::Name(key), value, 0)?;
+  Ok(if written { Some(writer.out) } else { None })
+}
+
+// A shallow copy of `obj`, like the `clone` that JS ssb-keys signs, and its
+// JSON, read in one pass
+pub fn clone_and_stringify<'a>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<'a, JsObject>,
+) -> NeonResult<(Handle<'a, JsObject>, String)> {
+  let copy = cx.empty_object();
+  let mut writer = Writer::new();
+  writer.object(cx, obj, 0, Top::CopyInto(copy))?;
+  Ok((copy, writer.out))
+}
+
+// The JSON of `obj` without its `signature` field, which is what was signed
+pub fn stringify_unsigned<'a>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<'a, JsObject>,
+) -> NeonResult<String> {
+  let mut writer = Writer::new();
+  writer.object(cx, obj, 0, Top::Skip("signature"))?;
+  Ok(writer.out)
+}
+
+// The JSON of a message with `signature` added as its last field, from the JSON
+// without it. JSON.stringify writes it just before the closing brace, after a
+// comma unless the object was empty and written as `{}`
+pub fn with_signature(unsigned: &str, signature: &str) -> String {
+  let mut out = match unsigned.strip_suffix("\n}") {
+    Some(fields) => format!("{},", fields),
+    None => unsigned.strip_suffix('}').unwrap_or(unsigned).to_string(),
+  };
+  out.push_str("\n  \"signature\": ");
+  write_escaped(&mut out, signature);
+  out.push_str("\n}");
+  out
+}
@@ -1,38 +1,123 @@
+mod atomic;
+mod create;
+mod derive;
+mod encrypted;
+mod errors;
 mod generate;
 mod hash;
+mod identity;
+mod jwk;
+mod legacy_json;
 mod load_create;
 mod misc;
+mod openssh;
+mod pem;
+mod permissions;
+mod pool;
+mod profiles;
+mod rotate;
 mod secret;
+mod secure_memory;
 mod sig;
 mod unbox;
 mod utils;
+mod validate;
+mod validate_feeds;
+mod vanity;
+mod verify_batch;
+mod wipe;
 
-use self::generate::neon_generate;
-use self::hash::neon_hash;
+use self::create::{neon_create_message, neon_create_message_batch};
+use self::derive::neon_derive_keys;
+use self::encrypted::{
+  neon_change_passphrase, neon_change_passphrase_sync, neon_create_encrypted,
+  neon_create_encrypted_sync, neon_load_encrypted, neon_load_encrypted_sync,
+};
+use self::generate::{neon_from_mnemonic, neon_generate, neon_generate_many, neon_to_mnemonic};
+use self::hash::{neon_hash, neon_hash_async, neon_msg_id, neon_msg_id_batch};
+use self::identity::neon_open_identity;
+use self::jwk::{neon_from_jwk, neon_to_jwk};
 use self::load_create::{
   neon_create, neon_create_sync, neon_load, neon_load_or_create, neon_load_or_create_sync,
   neon_load_sync,
 };
 use self::misc::neon_get_tag;
-use self::secret::{neon_secret_box, neon_secret_unbox};
-use self::sig::{neon_sign, neon_sign_obj, neon_verify, neon_verify_obj};
-use self::unbox::{neon_box, neon_sk_to_curve, neon_unbox, neon_unbox_body, neon_unbox_key};
+use self::openssh::{neon_from_openssh, neon_to_openssh};
+use self::pem::{neon_from_pem, neon_to_pem};
+use self::permissions::neon_audit_keyfile;
+use self::pool::{neon_get_thread_pool_size, neon_set_thread_pool_size};
+use self::profiles::{
+  neon_add_identity, neon_get_default, neon_list_identities, neon_remove_identity, neon_set_default,
+};
+use self::rotate::{neon_rotate_identity, neon_verify_rotation};
+use self::secret::{
+  neon_secret_box, neon_secret_box_async, neon_secret_unbox, neon_secret_unbox_async,
+};
+use self::secure_memory::{neon_enable_secure_memory, neon_secure_memory_status};
+use self::sig::{
+  neon_sign, neon_sign_async, neon_sign_obj, neon_sign_obj_async, neon_verify, neon_verify_async,
+  neon_verify_obj, neon_verify_obj_async,
+};
+use self::unbox::{
+  neon_box, neon_box_async, neon_sk_to_curve, neon_unbox, neon_unbox_async, neon_unbox_body,
+  neon_unbox_body_async, neon_unbox_key, neon_unbox_key_async,
+};
+use self::validate::{neon_validate, neon_validate_batch};
+use self::validate_feeds::neon_validate_feeds;
+use self::vanity::{neon_cancel_vanity, neon_generate_vanity};
+use self::verify_batch::{neon_verify_obj_batch, neon_verify_obj_batch_async};
 use neon::prelude::*;
 
 register_module!(mut cx, {
   cx.export_function("generate", neon_generate)?;
+  cx.export_function("generateMany", neon_generate_many)?;
+  cx.export_function("toMnemonic", neon_to_mnemonic)?;
+  cx.export_function("fromMnemonic", neon_from_mnemonic)?;
+  cx.export_function("deriveKeys", neon_derive_keys)?;
+  cx.export_function("generateVanity", neon_generate_vanity)?;
+  cx.export_function("cancelVanity", neon_cancel_vanity)?;
   cx.export_function("load", neon_load)?;
   cx.export_function("loadSync", neon_load_sync)?;
   cx.export_function("create", neon_create)?;
   cx.export_function("createSync", neon_create_sync)?;
   cx.export_function("loadOrCreate", neon_load_or_create)?;
   cx.export_function("loadOrCreateSync", neon_load_or_create_sync)?;
+  cx.export_function("createEncrypted", neon_create_encrypted)?;
+  cx.export_function("createEncryptedSync", neon_create_encrypted_sync)?;
+  cx.export_function("loadEncrypted", neon_load_encrypted)?;
+  cx.export_function("loadEncryptedSync", neon_load_encrypted_sync)?;
+  cx.export_function("changePassphrase", neon_change_passphrase)?;
+  cx.export_function("changePassphraseSync", neon_change_passphrase_sync)?;
+  cx.export_function("auditKeyfile", neon_audit_keyfile)?;
+  cx.export_function("rotateIdentity", neon_rotate_identity)?;
+  cx.export_function("verifyRotation", neon_verify_rotation)?;
+  cx.export_function("toOpenSSH", neon_to_openssh)?;
+  cx.export_function("fromOpenSSH", neon_from_openssh)?;
+  cx.export_function("toPEM", neon_to_pem)?;
+  cx.export_function("fromPEM", neon_from_pem)?;
+  cx.export_function("toJWK", neon_to_jwk)?;
+  cx.export_function("fromJWK", neon_from_jwk)?;
+  cx.export_function("listIdentities", neon_list_identities)?;
+  cx.export_function("addIdentity", neon_add_identity)?;
+  cx.export_function("removeIdentity", neon_remove_identity)?;
+  cx.export_function("getDefault", neon_get_default)?;
+  cx.export_function("setDefault", neon_set_default)?;
+  cx.export_function("openIdentity", neon_open_identity)?;
+  cx.export_function("enableSecureMemory", neon_enable_secure_memory)?;
+  cx.export_function("secureMemoryStatus", neon_secure_memory_status)?;
   cx.export_function("signObj", neon_sign_obj)?;
   cx.export_function("verifyObj", neon_verify_obj)?;
+  cx.export_function("verifyObjBatch", neon_verify_obj_batch)?;
   cx.export_function("sign", neon_sign)?;
   cx.export_function("verify", neon_verify)?;
   cx.export_function("getTag", neon_get_tag)?;
   cx.export_function("hash", neon_hash)?;
+  cx.export_function("msgId", neon_msg_id)?;
+  cx.export_function("msgIdBatch", neon_msg_id_batch)?;
+  cx.export_function("validate", neon_validate)?;
+  cx.export_function("validateBatch", neon_validate_batch)?;
+  cx.export_function("createMessage", neon_create_message)?;
+  cx.export_function("createMessageBatch", neon_create_message_batch)?;
   cx.export_function("box", neon_box)?;
   cx.export_function("unbox", neon_unbox)?;
   cx.export_function("unboxKey", neon_unbox_key)?;
@@ -40,5 +125,20 @@ register_module!(mut cx, {
   cx.export_function("ssbSecretKeyToPrivateBoxSecret", neon_sk_to_curve)?;
   cx.export_function("secretBox", neon_secret_box)?;
   cx.export_function("secretUnbox", neon_secret_unbox)?;
+  cx.export_function("signAsync", neon_sign_async)?;
+  cx.export_function("verifyAsync", neon_verify_async)?;
+  cx.export_function("signObjAsync", neon_sign_obj_async)?;
+  cx.export_function("verifyObjAsync", neon_verify_obj_async)?;
+  cx.export_function("verifyObjBatchAsync", neon_verify_obj_batch_async)?;
+  cx.export_function("hashAsync", neon_hash_async)?;
+  cx.export_function("boxAsync", neon_box_async)?;
+  cx.export_function("unboxAsync", neon_unbox_async)?;
+  cx.export_function("unboxKeyAsync", neon_unbox_key_async)?;
+  cx.export_function("unboxBodyAsync", neon_unbox_body_async)?;
+  cx.export_function("secretBoxAsync", neon_secret_box_async)?;
+  cx.export_function("secretUnboxAsync", neon_secret_unbox_async)?;
+  cx.export_function("setThreadPoolSize", neon_set_thread_pool_size)?;
+  cx.export_function("getThreadPoolSize", neon_get_thread_pool_size)?;
+  cx.export_function("validateFeeds", neon_validate_feeds)?;
   Ok(())
 });
@@ -1,30 +1,258 @@
-use super::utils::{make_keys_obj, ContextExt};
+use super::atomic::{write_keyfile, KeyfileLock};
+use super::encrypted;
+use super::errors::{self, CodedError};
+use super::permissions;
+use super::utils::{make_keys_obj, type_name, ContextExt, StringExt, ValueExt};
+use super::wipe::{self, SecretString};
 use neon::prelude::*;
 
+use serde::Deserialize;
 use ssb_crypto::Keypair;
 use ssb_keyfile::KeyFileError as SSBError;
+use zeroize::{Zeroize, Zeroizing};
 
-use std::io::Error;
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-fn internal_create<P: AsRef<Path>>(path: P) -> Result<Keypair, Error> {
+pub fn keyfile_path<P: AsRef<Path>>(path: P) -> PathBuf {
   if path.as_ref().is_dir() {
-    ssb_keyfile::generate_at_path(path.as_ref().join("secret"))
+    path.as_ref().join("secret")
   } else {
-    ssb_keyfile::generate_at_path(&path)
+    path.as_ref().to_path_buf()
   }
 }
 
-fn internal_load<P: AsRef<Path>>(path: P) -> Result<Keypair, SSBError> {
-  if path.as_ref().is_dir() {
-    ssb_keyfile::read_from_path(path.as_ref().join("secret"))
+// The same comments as in keyfiles written by ssb-keyfile and JS ssb-keys
+const PRE_COMMENT: &str = "# WARNING: Never show this to anyone.
+# WARNING: Never edit it or use it on multiple devices at once.
+#
+# This is your SECRET, it gives you magical powers. With your secret you can
+# sign your messages so that your friends can verify that the messages came
+# from you. If anyone learns your secret, they can use it to impersonate you.
+#
+# If you use this secret on more than one device you will create a fork and
+# your friends will stop replicating your content.
+#
+";
+const POST_COMMENT: &str = "
+#
+# The only part of this file that's safe to share is your public name:
+#
+#   ";
+
+// Byte for byte what `ssb_keyfile::write_to_string` returns, but built in a
+// buffer that is wiped. The legacy layout that JS ssb-keys writes when
+// `isLegacy` is true has the same comments, but only the private key string
+// instead of the JSON object
+pub fn keyfile_string(keypair: &Keypair, legacy: bool) -> SecretString {
+  let public = keypair.public.as_base64().with_suffix(".ed25519");
+  let id = keypair.public.as_base64().wrap('@', ".ed25519");
+  let private = wipe::keypair_to_base64(keypair, ".ed25519");
+
+  let mut out = Zeroizing::new(String::with_capacity(1024));
+  out.push_str(PRE_COMMENT);
+  if legacy {
+    out.push_str(&private);
   } else {
-    ssb_keyfile::read_from_path(path)
+    out.push_str("{\n  \"curve\": \"ed25519\",\n  \"public\": \"");
+    out.push_str(&public);
+    out.push_str("\",\n  \"private\": \"");
+    out.push_str(&private);
+    out.push_str("\",\n  \"id\": \"");
+    out.push_str(&id);
+    out.push_str("\"\n}");
+  }
+  out.push_str(POST_COMMENT);
+  out.push_str(&id);
+  out
+}
+
+// Only `private` matters, it also contains the public key
+#[derive(Deserialize)]
+struct SecretFile {
+  private: String,
+}
+
+impl Drop for SecretFile {
+  fn drop(&mut self) {
+    self.private.zeroize();
+  }
+}
+
+// Like `ssb_keyfile::read_from_str`, wiping the intermediate strings
+fn parse_keyfile(contents: &str) -> Result<Keypair, SSBError> {
+  let mut json = Zeroizing::new(String::with_capacity(contents.len()));
+  for line in contents.lines().filter(|line| !line.starts_with('#')) {
+    json.push_str(line);
+  }
+  let json = json.trim_matches(char::from(0));
+  match serde_json::from_str::<SecretFile>(json) {
+    Ok(file) => wipe::keypair_from_base64(&file.private).ok_or(SSBError::Decode),
+    // Maybe it's a legacy keyfile, which has only the private key string
+    Err(e) => wipe::keypair_from_base64(json.trim()).ok_or(SSBError::Json(e)),
+  }
+}
+
+pub fn internal_create<P: AsRef<Path>>(path: P, legacy: bool) -> Result<Keypair, Error> {
+  let path = keyfile_path(path);
+  let keypair = Keypair::generate();
+  let contents = keyfile_string(&keypair, legacy);
+  write_keyfile(&path, contents.as_bytes())?;
+  Ok(keypair)
+}
+
+pub enum LoadError {
+  KeyFile(SSBError),
+  Encrypted,
+  Refused(String),
+  Io(Error),
+}
+
+impl From<SSBError> for LoadError {
+  fn from(e: SSBError) -> Self {
+    LoadError::KeyFile(e)
+  }
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::KeyFile(SSBError::FileRead(e)) => write!(f, "Failed to read from file: {}", e),
+      LoadError::KeyFile(e) => write!(f, "{}", e),
+      LoadError::Encrypted => write!(
+        f,
+        "encrypted keyfile, passphrase required (use `loadEncrypted`)"
+      ),
+      LoadError::Refused(reason) => write!(f, "{}", reason),
+      LoadError::Io(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+pub fn internal_load<P: AsRef<Path>>(path: P) -> Result<Keypair, LoadError> {
+  let contents = wipe::read_to_string(keyfile_path(path)).map_err(SSBError::from)?;
+  if encrypted::is_encrypted(&contents) {
+    return Err(LoadError::Encrypted);
+  }
+  Ok(parse_keyfile(&contents)?)
+}
+
+// The `code` of errors from creating a keyfile
+pub fn write_error_code(e: &Error) -> &'static str {
+  match e.kind() {
+    ErrorKind::AlreadyExists => errors::KEYFILE_EXISTS,
+    _ => errors::KEYFILE_WRITE,
+  }
+}
+
+impl LoadError {
+  pub fn code(&self) -> &'static str {
+    match self {
+      LoadError::KeyFile(SSBError::FileRead(e)) => match e.kind() {
+        ErrorKind::NotFound => errors::KEYFILE_NOT_FOUND,
+        ErrorKind::InvalidData => errors::KEYFILE_CORRUPT,
+        _ => errors::KEYFILE_READ,
+      },
+      LoadError::KeyFile(_) => errors::KEYFILE_CORRUPT,
+      LoadError::Encrypted => errors::KEYFILE_ENCRYPTED,
+      LoadError::Refused(_) => errors::KEYFILE_INSECURE,
+      LoadError::Io(e) => write_error_code(e),
+    }
+  }
+
+  fn is_not_found(&self) -> bool {
+    match self {
+      LoadError::KeyFile(SSBError::FileRead(e)) => e.kind() == ErrorKind::NotFound,
+      _ => false,
+    }
+  }
+
+  // The keyfile exists and is readable, but doesn't contain a keypair
+  fn is_corrupt(&self) -> bool {
+    match self {
+      LoadError::KeyFile(SSBError::FileRead(e)) => e.kind() == ErrorKind::InvalidData,
+      LoadError::KeyFile(_) => true,
+      _ => false,
+    }
+  }
+}
+
+// Renames the keyfile to `<name>.corrupt-<timestamp>`, so that it can still
+// be inspected or recovered by hand
+fn move_aside(path: &Path) -> Result<PathBuf, Error> {
+  let millis = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or(0);
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(format!(".corrupt-{}", millis));
+  let target = path.with_file_name(name);
+  if target.exists() {
+    return Err(Error::new(
+      ErrorKind::AlreadyExists,
+      format!(
+        "cannot move corrupt keyfile to {}, it exists",
+        target.display()
+      ),
+    ));
+  }
+  fs::rename(path, &target)?;
+  Ok(target)
+}
+
+// Creates a new keyfile only when there is none. With `recover`, a corrupt
+// keyfile is moved aside first, otherwise its error is returned as is.
+// Holds the keyfile lock so that concurrent callers, even in other processes,
+// all end up with the keypair that the first one created
+fn internal_load_or_create<P: AsRef<Path>>(path: P, recover: bool) -> Result<Keypair, LoadError> {
+  let path = keyfile_path(path);
+  let _lock = KeyfileLock::acquire(&path).map_err(LoadError::Io)?;
+  match internal_load(&path) {
+    Err(ref e) if e.is_not_found() => {}
+    Err(ref e) if recover && e.is_corrupt() => {
+      move_aside(&path).map_err(LoadError::Io)?;
+    }
+    result => return result,
+  }
+  match internal_create(&path, false) {
+    // Where the lock does nothing, another caller may have created it since
+    Err(e) if e.kind() == ErrorKind::AlreadyExists => internal_load(&path),
+    result => result.map_err(LoadError::Io),
+  }
+}
+
+// Reads the optional `curve` argument, which can only be "ed25519"
+fn check_curve(cx: &mut FunctionContext, index: i32) -> NeonResult<()> {
+  match cx.argument_opt(index) {
+    Some(v) if v.is_a::<JsString>() => {
+      let curve = v.downcast::<JsString>().unwrap().value();
+      if curve != "ed25519" {
+        return CodedError::new(errors::BAD_CURVE, "curve argument only supports: ed25519")
+          .arg(index)
+          .throw(cx);
+      }
+      Ok(())
+    }
+    Some(v) if !(v.is_a::<JsUndefined>() || v.is_a::<JsNull>()) => CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      format!("curve argument must be a string, was a {}", type_name(&v)),
+    )
+    .arg(index)
+    .throw(cx),
+    _ => Ok(()),
   }
 }
 
+pub fn load_error(e: &LoadError, path: &str) -> CodedError {
+  CodedError::new(e.code(), e.to_string()).field("path", path)
+}
+
 struct CreateTask {
   argument: String,
+  legacy: bool,
 }
 
 impl Task for CreateTask {
@@ -33,11 +261,15 @@ impl Task for CreateTask {
   type JsEvent = JsObject;
 
   fn perform(&self) -> Result<Keypair, Error> {
-    internal_create(&self.argument)
+    internal_create(&self.argument, self.legacy)
   }
 
   fn complete(self, mut cx: TaskContext, result: Result<Keypair, Error>) -> JsResult<JsObject> {
-    let keypair = result.or_else(|e| cx.throw_error(e.to_string()))?;
+    let keypair = result.or_else(|e| {
+      CodedError::new(write_error_code(&e), e.to_string())
+        .field("path", &self.argument)
+        .throw(&mut cx)
+    })?;
 
     make_keys_obj(&mut cx, &keypair)
   }
@@ -45,19 +277,23 @@ impl Task for CreateTask {
 
 struct LoadTask {
   argument: String,
+  refused: Option<String>,
 }
 
 impl Task for LoadTask {
   type Output = Keypair;
-  type Error = SSBError;
+  type Error = LoadError;
   type JsEvent = JsObject;
 
-  fn perform(&self) -> Result<Keypair, SSBError> {
+  fn perform(&self) -> Result<Keypair, LoadError> {
+    if let Some(reason) = &self.refused {
+      return Err(LoadError::Refused(reason.clone()));
+    }
     internal_load(&self.argument)
   }
 
-  fn complete(self, mut cx: TaskContext, result: Result<Keypair, SSBError>) -> JsResult<JsObject> {
-    let keypair = result.or_else(|e| cx.throw_error(e.to_string()))?;
+  fn complete(self, mut cx: TaskContext, result: Result<Keypair, LoadError>) -> JsResult<JsObject> {
+    let keypair = result.or_else(|e| load_error(&e, &self.argument).throw(&mut cx))?;
 
     make_keys_obj(&mut cx, &keypair)
   }
@@ -65,89 +301,147 @@ impl Task for LoadTask {
 
 struct LoadOrCreateTask {
   argument: String,
+  recover: bool,
 }
 
 impl Task for LoadOrCreateTask {
   type Output = Keypair;
-  type Error = Error;
+  type Error = LoadError;
   type JsEvent = JsObject;
 
-  fn perform(&self) -> Result<Keypair, Error> {
-    internal_load(&self.argument).or_else(|_| internal_create(&self.argument))
+  fn perform(&self) -> Result<Keypair, LoadError> {
+    internal_load_or_create(&self.argument, self.recover)
   }
 
-  fn complete(self, mut cx: TaskContext, result: Result<Keypair, Error>) -> JsResult<JsObject> {
-    let keypair = result.or_else(|e| cx.throw_error(e.to_string()))?;
+  fn complete(self, mut cx: TaskContext, result: Result<Keypair, LoadError>) -> JsResult<JsObject> {
+    let keypair = result.or_else(|e| load_error(&e, &self.argument).throw(&mut cx))?;
 
     make_keys_obj(&mut cx, &keypair)
   }
 }
 
+// create: (path: string, curve?: string, isLegacy?: boolean, cb: fn) => void
 pub fn neon_create(mut cx: FunctionContext) -> JsResult<JsUndefined> {
-  // TODO support all arguments (path, curve, isLegacy, cb)
+  let argc = cx.len();
   let path = cx
     .arg_as::<JsString>(0, "expected string as the first argument to `create`")?
     .value();
 
-  let cb = cx.arg_as::<JsFunction>(1, "expected a callback function given to `create`")?;
-  let task = CreateTask { argument: path };
+  // Like in ssb-keys, the callback is always the last argument
+  let cb = cx.arg_as::<JsFunction>(argc - 1, "expected a callback function given to `create`")?;
+  if argc > 2 {
+    check_curve(&mut cx, 1)?;
+  }
+  let legacy = argc > 3 && cx.argument::<JsValue>(2)?.is_truthy(&mut cx);
+
+  let task = CreateTask {
+    argument: path,
+    legacy,
+  };
   task.schedule(cb);
   Ok(cx.undefined())
 }
 
+// createSync: (path: string, curve?: string, isLegacy?: boolean) => obj
 pub fn neon_create_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
-  // TODO support all arguments (path, curve, isLegacy)
   let path = cx
-    .arg_as::<JsString>(0, "expected string as only argument to `loadSync`")?
+    .arg_as::<JsString>(0, "expected string as the first argument to `createSync`")?
     .value();
-
-  let keypair = internal_create(&path).or_else(|e| cx.throw_error(e.to_string()))?;
+  check_curve(&mut cx, 1)?;
+  let legacy = match cx.argument_opt(2) {
+    Some(v) => v.is_truthy(&mut cx),
+    None => false,
+  };
+
+  let keypair = internal_create(&path, legacy).or_else(|e| {
+    CodedError::new(write_error_code(&e), e.to_string())
+      .field("path", &path)
+      .throw(&mut cx)
+  })?;
 
   make_keys_obj(&mut cx, &keypair)
 }
 
+// load: (path: string, opts?: {permissions?: string, onWarning?: fn}, cb: fn) => void
 pub fn neon_load(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let argc = cx.len();
   let path = cx
     .arg_as::<JsString>(0, "expected string as the first argument to `load`")?
     .value();
 
-  let cb = cx.arg_as::<JsFunction>(1, "expected a callback function given to `load`")?;
+  let cb = cx.arg_as::<JsFunction>(argc - 1, "expected a callback function given to `load`")?;
+  let opts = if argc > 2 { cx.argument_opt(1) } else { None };
+  let refused = permissions::enforce(&mut cx, &path, opts)?;
 
-  let task = LoadTask { argument: path };
+  let task = LoadTask {
+    argument: path,
+    refused,
+  };
   task.schedule(cb);
   Ok(cx.undefined())
 }
 
+// loadSync: (path: string, opts?: {permissions?: string, onWarning?: fn}) => obj
 pub fn neon_load_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
   let path = cx
-    .arg_as::<JsString>(0, "expected string as only argument to `loadSync`")?
+    .arg_as::<JsString>(0, "expected string as the first argument to `loadSync`")?
     .value();
 
-  let keypair = internal_load(&path).or_else(|e| cx.throw_error(e.to_string()))?;
+  let opts = cx.argument_opt(1);
+  if let Some(reason) = permissions::enforce(&mut cx, &path, opts)? {
+    return load_error(&LoadError::Refused(reason), &path).throw(&mut cx);
+  }
+
+  let keypair = internal_load(&path).or_else(|e| load_error(&e, &path).throw(&mut cx))?;
 
   make_keys_obj(&mut cx, &keypair)
 }
 
+// Reads the truthiness of `opts.recover` in the optional opts argument
+fn recover_opt(cx: &mut FunctionContext, index: i32) -> NeonResult<bool> {
+  match cx.argument_opt(index) {
+    Some(v) if v.is_a::<JsObject>() => {
+      let opts = v.downcast::<JsObject>().unwrap();
+      let recover: Handle<JsValue> = opts.get(cx, "recover")?;
+      Ok(recover.is_truthy(cx))
+    }
+    _ => Ok(false),
+  }
+}
+
+// loadOrCreate: (path: string, opts?: {recover?: boolean}, cb: fn) => void
 pub fn neon_load_or_create(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let argc = cx.len();
   let path = cx
     .arg_as::<JsString>(0, "expected string as the first argument to `loadOrCreate`")?
     .value();
 
-  let cb = cx.arg_as::<JsFunction>(1, "expected a callback function given to `loadOrCreate`")?;
+  let cb = cx.arg_as::<JsFunction>(
+    argc - 1,
+    "expected a callback function given to `loadOrCreate`",
+  )?;
+  let recover = argc > 2 && recover_opt(&mut cx, 1)?;
 
-  let task = LoadOrCreateTask { argument: path };
+  let task = LoadOrCreateTask {
+    argument: path,
+    recover,
+  };
   task.schedule(cb);
   Ok(cx.undefined())
 }
 
+// loadOrCreateSync: (path: string, opts?: {recover?: boolean}) => obj
 pub fn neon_load_or_create_sync(mut cx: FunctionContext) -> JsResult<JsObject> {
   let path = cx
-    .arg_as::<JsString>(0, "expected string as only argument to `loadOrCreateSync`")?
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `loadOrCreateSync`",
+    )?
     .value();
+  let recover = recover_opt(&mut cx, 1)?;
 
-  let keypair = internal_load(&path)
-    .or_else(|_| internal_create(&path))
-    .or_else(|e| cx.throw_error(e.to_string()))?;
+  let keypair =
+    internal_load_or_create(&path, recover).or_else(|e| load_error(&e, &path).throw(&mut cx))?;
 
   make_keys_obj(&mut cx, &keypair)
 }
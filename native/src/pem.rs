@@ -0,0 +1,262 @@
+use super::errors::{self, CodedError};
+use super::utils::{self, make_keys_obj, make_public_keys_obj, ContextExt};
+use super::wipe::{self, SecretBytes, SecretString};
+use neon::prelude::*;
+use ssb_crypto::{Keypair, PublicKey};
+use zeroize::Zeroizing;
+
+use std::fmt;
+
+// id-Ed25519 from RFC 8410, i.e. OID 1.3.101.112
+const ED25519_OID: &[u8] = &[0x2b, 0x65, 0x70];
+const PEM_LINE_WIDTH: usize = 64;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_ATTRIBUTES: u8 = 0xa0;
+const TAG_PUBLIC_KEY: u8 = 0x81;
+
+pub enum PemError {
+  NotPem,
+  Base64,
+  Asn1(&'static str),
+  UnsupportedAlgorithm,
+  KeyMismatch,
+}
+
+impl fmt::Display for PemError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PemError::NotPem => write!(f, "input is not a PRIVATE KEY or PUBLIC KEY PEM"),
+      PemError::Base64 => write!(f, "PEM body is not valid base64"),
+      PemError::Asn1(what) => write!(f, "malformed ASN.1 in PEM: {}", what),
+      PemError::UnsupportedAlgorithm => {
+        write!(f, "PEM key algorithm is not Ed25519 (OID 1.3.101.112)")
+      }
+      PemError::KeyMismatch => write!(f, "PEM public key does not match the private key"),
+    }
+  }
+}
+
+impl PemError {
+  // Errors about the PEM given as the first argument
+  fn coded(&self) -> CodedError {
+    let err = match self {
+      PemError::NotPem | PemError::Base64 | PemError::Asn1(_) => {
+        CodedError::type_error(errors::BAD_ENCODING, self.to_string())
+      }
+      PemError::UnsupportedAlgorithm => {
+        CodedError::range_error(errors::BAD_ENCODING, self.to_string())
+      }
+      PemError::KeyMismatch => CodedError::range_error(errors::BAD_PRIVATE_KEY, self.to_string()),
+    };
+    err.arg(0)
+  }
+}
+
+pub enum PemKey {
+  Private(Keypair),
+  Public(PublicKey),
+}
+
+// Just enough DER to read the two structures in RFC 8410
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+  fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  fn peek_tag(&self) -> Option<u8> {
+    self.0.first().copied()
+  }
+
+  fn read(&mut self, tag: u8, what: &'static str) -> Result<&'a [u8], PemError> {
+    let data = self.0;
+    if data.len() < 2 || data[0] != tag {
+      return Err(PemError::Asn1(what));
+    }
+    let (len, header) = match data[1] {
+      n if n < 0x80 => (n as usize, 2),
+      0x81 if data.len() > 2 => (data[2] as usize, 3),
+      0x82 if data.len() > 3 => (((data[2] as usize) << 8) | data[3] as usize, 4),
+      _ => return Err(PemError::Asn1("unsupported length")),
+    };
+    if data.len() < header + len {
+      return Err(PemError::Asn1("unexpected end of data"));
+    }
+    self.0 = &data[header + len..];
+    Ok(&data[header..header + len])
+  }
+
+  fn finish(&self) -> Result<(), PemError> {
+    if self.is_empty() {
+      Ok(())
+    } else {
+      Err(PemError::Asn1("trailing data"))
+    }
+  }
+}
+
+// Wiped on drop, since the PKCS#8 structures contain the secret key
+fn der(tag: u8, content: &[u8]) -> SecretBytes {
+  // Every structure here is shorter than 128 bytes
+  let mut out = Zeroizing::new(Vec::with_capacity(content.len() + 2));
+  out.push(tag);
+  out.push(content.len() as u8);
+  out.extend_from_slice(content);
+  out
+}
+
+fn algorithm_identifier() -> SecretBytes {
+  der(TAG_SEQUENCE, &der(TAG_OID, ED25519_OID))
+}
+
+fn read_algorithm(outer: &mut Der) -> Result<(), PemError> {
+  let mut alg = Der(outer.read(TAG_SEQUENCE, "expected AlgorithmIdentifier")?);
+  if alg.read(TAG_OID, "expected algorithm OID")? != ED25519_OID {
+    return Err(PemError::UnsupportedAlgorithm);
+  }
+  // Parameters must be absent for Ed25519
+  alg.finish()
+}
+
+fn read_bit_string<'a>(outer: &mut Der<'a>, tag: u8) -> Result<&'a [u8], PemError> {
+  match outer.read(tag, "expected public key bit string")? {
+    [0, key @ ..] => Ok(key),
+    _ => Err(PemError::Asn1("public key bit string has unused bits")),
+  }
+}
+
+fn pkcs8_der(keypair: &Keypair) -> SecretBytes {
+  let mut content = Zeroizing::new(Vec::with_capacity(64));
+  content.extend_from_slice(&der(TAG_INTEGER, &[0]));
+  content.extend_from_slice(&algorithm_identifier());
+  content.extend_from_slice(&der(
+    TAG_OCTET_STRING,
+    &der(TAG_OCTET_STRING, &keypair.secret.0),
+  ));
+  der(TAG_SEQUENCE, &content)
+}
+
+fn spki_der(public: &PublicKey) -> SecretBytes {
+  let mut content = Zeroizing::new(Vec::with_capacity(64));
+  content.extend_from_slice(&algorithm_identifier());
+  content.extend_from_slice(&der(TAG_BIT_STRING, &[&[0u8][..], &public.0[..]].concat()));
+  der(TAG_SEQUENCE, &content)
+}
+
+// PKCS#8 v1 and v2 (OneAsymmetricKey, RFC 5958), where v2 also has the public key
+fn parse_pkcs8(data: &[u8]) -> Result<Keypair, PemError> {
+  let mut outer = Der(data);
+  let mut seq = Der(outer.read(TAG_SEQUENCE, "expected PrivateKeyInfo sequence")?);
+  outer.finish()?;
+  match seq.read(TAG_INTEGER, "expected version")? {
+    [0] | [1] => {}
+    _ => return Err(PemError::Asn1("unsupported PrivateKeyInfo version")),
+  }
+  read_algorithm(&mut seq)?;
+  let mut private = Der(seq.read(TAG_OCTET_STRING, "expected private key octet string")?);
+  let seed = private.read(TAG_OCTET_STRING, "expected CurvePrivateKey octet string")?;
+  private.finish()?;
+  let keypair = Some(seed)
+    .filter(|seed| seed.len() == 32)
+    .and_then(Keypair::from_seed)
+    .ok_or(PemError::Asn1("Ed25519 private key must be 32 bytes"))?;
+
+  if seq.peek_tag() == Some(TAG_ATTRIBUTES) {
+    seq.read(TAG_ATTRIBUTES, "expected attributes")?;
+  }
+  if seq.peek_tag() == Some(TAG_PUBLIC_KEY)
+    && read_bit_string(&mut seq, TAG_PUBLIC_KEY)? != keypair.public.0
+  {
+    return Err(PemError::KeyMismatch);
+  }
+  seq.finish()?;
+  Ok(keypair)
+}
+
+fn parse_spki(data: &[u8]) -> Result<PublicKey, PemError> {
+  let mut outer = Der(data);
+  let mut seq = Der(outer.read(TAG_SEQUENCE, "expected SubjectPublicKeyInfo sequence")?);
+  outer.finish()?;
+  read_algorithm(&mut seq)?;
+  let key = read_bit_string(&mut seq, TAG_BIT_STRING)?;
+  seq.finish()?;
+  PublicKey::from_slice(key).ok_or(PemError::Asn1("Ed25519 public key must be 32 bytes"))
+}
+
+fn to_pem(label: &str, der: &[u8]) -> SecretString {
+  let b64 = wipe::encode_base64(der, base64::STANDARD, "");
+  // The BEGIN and END lines, plus the body with a newline per line
+  let capacity = 2 * label.len() + 32 + b64.len() + b64.len() / PEM_LINE_WIDTH + 1;
+  let mut pem = Zeroizing::new(String::with_capacity(capacity));
+  pem.push_str(&format!("-----BEGIN {}-----\n", label));
+  for line in b64.as_bytes().chunks(PEM_LINE_WIDTH) {
+    pem.push_str(std::str::from_utf8(line).unwrap()); // base64 is ascii
+    pem.push('\n');
+  }
+  pem.push_str(&format!("-----END {}-----\n", label));
+  pem
+}
+
+fn from_pem(text: &str) -> Result<PemKey, PemError> {
+  let text = text.trim();
+  let unwrap_label = |label: &str| {
+    text
+      .strip_prefix(&format!("-----BEGIN {}-----", label))
+      .and_then(|rest| rest.strip_suffix(&format!("-----END {}-----", label)))
+      .map(|body| {
+        let mut b64 = Zeroizing::new(String::with_capacity(body.len()));
+        body.split_whitespace().for_each(|part| b64.push_str(part));
+        b64
+      })
+  };
+  let decode =
+    |b64: SecretString| wipe::decode_base64(&b64, base64::STANDARD).ok_or(PemError::Base64);
+
+  if let Some(b64) = unwrap_label("PRIVATE KEY") {
+    parse_pkcs8(&decode(b64)?).map(PemKey::Private)
+  } else if let Some(b64) = unwrap_label("PUBLIC KEY") {
+    parse_spki(&decode(b64)?).map(PemKey::Public)
+  } else {
+    Err(PemError::NotPem)
+  }
+}
+
+// toPEM: (keys: obj | string) => {private: string, public: string}
+pub fn neon_to_pem(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let keypair = utils::keypair_arg(
+    &mut cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
+
+  let result = cx.empty_object();
+  let private = cx.string(&*to_pem("PRIVATE KEY", &pkcs8_der(&keypair)));
+  let public = cx.string(&*to_pem("PUBLIC KEY", &spki_der(&keypair.public)));
+  result.set(&mut cx, "private", private)?;
+  result.set(&mut cx, "public", public)?;
+  Ok(result)
+}
+
+// fromPEM: (pem: string) => obj, without `private` for a PUBLIC KEY pem
+pub fn neon_from_pem(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let text = Zeroizing::new(
+    cx.arg_as::<JsString>(
+      0,
+      "expected the PEM string as the only argument to `fromPEM`",
+    )?
+    .value(),
+  );
+
+  match from_pem(&text) {
+    Ok(PemKey::Private(keypair)) => make_keys_obj(&mut cx, &keypair),
+    Ok(PemKey::Public(public)) => make_public_keys_obj(&mut cx, &public),
+    Err(e) => e.coded().throw(&mut cx),
+  }
+}
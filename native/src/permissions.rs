@@ -0,0 +1,194 @@
+use super::errors::{self, CodedError};
+use super::load_create::keyfile_path;
+use super::utils::{call_builtin, get_field_opt, ContextExt, HandleExt};
+use arrayvec::ArrayVec;
+use neon::prelude::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct Problem {
+  pub kind: &'static str,
+  pub message: String,
+}
+
+// What `load` does when `auditKeyfile` finds problems
+pub enum Policy {
+  Ignore,
+  Warn,
+  Refuse,
+}
+
+pub fn audit<P: AsRef<Path>>(path: P) -> io::Result<Vec<Problem>> {
+  let path = keyfile_path(path);
+  let mut problems = vec![];
+
+  if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+    problems.push(Problem {
+      kind: "symlink",
+      message: format!("keyfile {} is a symlink", path.display()),
+    });
+  }
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(&path)?;
+    let mode = meta.mode() & 0o777;
+    if mode != 0o600 && mode != 0o400 {
+      problems.push(Problem {
+        kind: "mode",
+        message: format!(
+          "keyfile {} has mode {:04o}, but it should be 0600 or 0400",
+          path.display(),
+          mode
+        ),
+      });
+    }
+
+    let euid = unsafe { libc::geteuid() };
+    if meta.uid() != euid {
+      problems.push(Problem {
+        kind: "owner",
+        message: format!(
+          "keyfile {} is owned by uid {}, not by the current user (uid {})",
+          path.display(),
+          meta.uid(),
+          euid
+        ),
+      });
+    }
+
+    let dir = match path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+    let dir_mode = fs::metadata(dir)?.mode();
+    if dir_mode & 0o022 != 0 {
+      let who = if dir_mode & 0o002 != 0 {
+        "world"
+      } else {
+        "group"
+      };
+      problems.push(Problem {
+        kind: "parent",
+        message: format!("keyfile directory {} is {}-writable", dir.display(), who),
+      });
+    }
+  }
+
+  Ok(problems)
+}
+
+fn problems_to_js<'a>(cx: &mut impl Context<'a>, problems: &[Problem]) -> JsResult<'a, JsArray> {
+  let arr = JsArray::new(cx, problems.len() as u32);
+  for (i, problem) in problems.iter().enumerate() {
+    let obj = cx.empty_object();
+    let kind = cx.string(problem.kind);
+    let message = cx.string(&problem.message);
+    obj.set(cx, "kind", kind)?;
+    obj.set(cx, "message", message)?;
+    arr.set(cx, i as u32, obj)?;
+  }
+  Ok(arr)
+}
+
+fn read_policy<'a>(
+  cx: &mut FunctionContext<'a>,
+  opts: Handle<'a, JsObject>,
+) -> NeonResult<(Policy, Option<Handle<'a, JsFunction>>)> {
+  let msg = "opts.permissions must be one of \"ignore\", \"warn\" or \"refuse\"";
+  // The options are the 2nd argument of both `load` and `loadSync`
+  let policy = match get_field_opt::<JsString>(cx, opts, 1, "permissions", msg)? {
+    None => Policy::Ignore,
+    Some(s) => match s.value().as_str() {
+      "ignore" => Policy::Ignore,
+      "warn" => Policy::Warn,
+      "refuse" => Policy::Refuse,
+      _ => {
+        return CodedError::range_error(errors::INVALID_ARG_VALUE, msg)
+          .arg(1)
+          .throw(cx)
+      }
+    },
+  };
+  let on_warning = get_field_opt::<JsFunction>(
+    cx,
+    opts,
+    1,
+    "onWarning",
+    "opts.onWarning must be a function",
+  )?;
+  Ok((policy, on_warning))
+}
+
+// Audits the keyfile according to `opts.permissions`, calling `opts.onWarning`
+// (or `process.emitWarning`) when warning. Returns the reason to refuse loading.
+// Missing or unreadable keyfiles are left for the loader itself to report.
+pub fn enforce<'a>(
+  cx: &mut FunctionContext<'a>,
+  path: &str,
+  opts: Option<Handle<'a, JsValue>>,
+) -> NeonResult<Option<String>> {
+  let opts = match opts.and_then(|v| v.try_downcast::<JsObject>()) {
+    Some(opts) => opts,
+    None => return Ok(None),
+  };
+  let (policy, on_warning) = read_policy(cx, opts)?;
+  if let Policy::Ignore = policy {
+    return Ok(None);
+  }
+  let problems = match audit(path) {
+    Ok(problems) if !problems.is_empty() => problems,
+    _ => return Ok(None),
+  };
+  let summary = problems
+    .iter()
+    .map(|problem| problem.message.as_str())
+    .collect::<Vec<&str>>()
+    .join("; ");
+
+  match (policy, on_warning) {
+    (Policy::Refuse, _) => Ok(Some(format!(
+      "refusing to load insecure keyfile: {}",
+      summary
+    ))),
+    (_, Some(on_warning)) => {
+      let arr = problems_to_js(cx, &problems)?;
+      let null = cx.null();
+      on_warning.call(cx, null, ArrayVec::from([arr.upcast()]))?;
+      Ok(None)
+    }
+    (_, None) => {
+      let warning = cx.string(format!("insecure keyfile: {}", summary));
+      call_builtin::<JsUndefined>(
+        cx,
+        "process",
+        "emitWarning",
+        ArrayVec::from([warning.upcast()]),
+      )?;
+      Ok(None)
+    }
+  }
+}
+
+// auditKeyfile: (path: string) => Array<{kind: string, message: string}>
+pub fn neon_audit_keyfile(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let path = cx
+    .arg_as::<JsString>(0, "expected string as only argument to `auditKeyfile`")?
+    .value();
+
+  let problems = audit(&path).or_else(|e| {
+    let code = match e.kind() {
+      io::ErrorKind::NotFound => errors::KEYFILE_NOT_FOUND,
+      _ => errors::KEYFILE_READ,
+    };
+    CodedError::new(code, e.to_string())
+      .field("path", &path)
+      .throw(&mut cx)
+  })?;
+
+  problems_to_js(&mut cx, &problems)
+}
@@ -0,0 +1,138 @@
+use super::errors::{self, CodedError};
+use super::utils::ContextExt;
+use neon::context::TaskContext;
+use neon::event::EventHandler;
+use neon::prelude::*;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// The native threads that the `*Async` functions run on. They are separate
+// from libuv's threadpool, which `fs` and the callback functions use, so a
+// burst of CPU heavy work can't starve those, and there is a bounded number
+static POOL: Mutex<Option<Pool>> = Mutex::new(None);
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+  sender: Sender<Job>,
+  size: usize,
+}
+
+impl Pool {
+  fn new(size: usize) -> Pool {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..size {
+      let receiver = receiver.clone();
+      // Each thread exits once the pool is replaced and its queue is drained
+      thread::spawn(move || loop {
+        let job = match receiver.lock().unwrap().recv() {
+          Ok(job) => job,
+          Err(_) => break,
+        };
+        job();
+      });
+    }
+    Pool { sender, size }
+  }
+}
+
+fn default_size() -> usize {
+  thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Runs `job` on one of the threads
+pub fn submit(job: Job) {
+  let mut pool = POOL.lock().unwrap();
+  let pool = pool.get_or_insert_with(|| Pool::new(default_size()));
+  // The threads only stop receiving when the sender is dropped
+  pool.sender.send(job).ok();
+}
+
+// Runs `work` on the pool, then `complete` with its output back on the main
+// thread, and calls `callback` with `(err, value)` like a `Task` does. `this`
+// is kept alive until then, so `complete` can use it
+pub fn spawn<'a, W, O, C>(
+  cx: &mut FunctionContext<'a>,
+  this: Handle<'a, JsValue>,
+  callback: Handle<'a, JsFunction>,
+  work: W,
+  complete: C,
+) where
+  W: FnOnce() -> O + Send + 'static,
+  O: Send + 'static,
+  C: for<'b> FnOnce(&mut TaskContext<'b>, Handle<'b, JsValue>, O) -> JsResult<'b, JsValue>,
+  C: Send + 'static,
+{
+  let handler = EventHandler::new(cx, this, callback);
+  submit(Box::new(move || {
+    let output = panic::catch_unwind(AssertUnwindSafe(work));
+    deliver(handler, output, complete);
+  }));
+}
+
+// Back on the main thread, calls the callback with the completed output or
+// with the error that a panic became
+fn deliver<O, C>(handler: EventHandler, output: thread::Result<O>, complete: C)
+where
+  O: Send + 'static,
+  C: for<'b> FnOnce(&mut TaskContext<'b>, Handle<'b, JsValue>, O) -> JsResult<'b, JsValue>,
+  C: Send + 'static,
+{
+  handler.schedule_with(move |cx, this, callback| {
+    let this = this.as_value(cx);
+    let result = cx.try_catch(|cx| match output {
+      Ok(output) => complete(cx, this, output),
+      Err(_) => errors::throw_code(cx, errors::INTERNAL, "native task panicked"),
+    });
+    let args = match result {
+      Ok(value) => vec![cx.null().upcast(), value],
+      Err(err) => vec![err],
+    };
+    let null = cx.null();
+    let _ = callback.call(cx, null, args);
+  });
+}
+
+// Reads the callback that the `*Async` functions take as their last argument
+pub fn callback_arg<'a>(cx: &mut FunctionContext<'a>, name: &str) -> JsResult<'a, JsFunction> {
+  let index = cx.len().max(1) - 1;
+  cx.arg_as::<JsFunction>(
+    index,
+    &format!("expected a callback function given to `{}`", name),
+  )
+}
+
+// setThreadPoolSize: (size: number) => void
+pub fn neon_set_thread_pool_size(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let size = cx
+    .arg_as::<JsNumber>(
+      0,
+      "expected a number as the only argument to `setThreadPoolSize`",
+    )?
+    .value();
+  if !(1.0..=1024.0).contains(&size) || size.fract() != 0.0 {
+    return CodedError::range_error(
+      errors::INVALID_ARG_VALUE,
+      "thread pool size must be an integer from 1 to 1024",
+    )
+    .arg(0)
+    .throw(&mut cx);
+  }
+  // Jobs already queued still run, on the threads of the old pool
+  *POOL.lock().unwrap() = Some(Pool::new(size as usize));
+  Ok(cx.undefined())
+}
+
+// getThreadPoolSize: () => number
+pub fn neon_get_thread_pool_size(mut cx: FunctionContext) -> JsResult<JsNumber> {
+  let size = POOL
+    .lock()
+    .unwrap()
+    .as_ref()
+    .map_or_else(default_size, |pool| pool.size);
+  Ok(cx.number(size as f64))
+}
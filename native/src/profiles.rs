@@ -0,0 +1,340 @@
+use super::atomic::{replace_keyfile, write_keyfile, KeyfileLock};
+use super::errors::{self, CodedError};
+use super::load_create::keyfile_string;
+use super::utils::{ContextExt, StringExt};
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use ssb_crypto::Keypair;
+
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Layout of a profiles directory:
+//
+//   <dir>/default                     id of the default identity
+//   <dir>/<name>/secret               a normal keyfile, so `load(<dir>/<name>)` works
+//   <dir>/<name>/profile.json         the manifest below
+//   <dir>/.removed/<name>-<timestamp> profiles moved away by `removeIdentity`
+const DEFAULT_FILE: &str = "default";
+const MANIFEST_FILE: &str = "profile.json";
+const REMOVED_DIR: &str = ".removed";
+const LOCK_NAME: &str = "profiles";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+  id: String,
+  label: String,
+  created: u64,
+}
+
+struct Profile {
+  manifest: Manifest,
+  path: PathBuf,
+}
+
+enum ProfileError {
+  Io(Error),
+  Corrupt(PathBuf, String),
+  BadLabel(String),
+  NoIdentity(String),
+}
+
+impl From<Error> for ProfileError {
+  fn from(e: Error) -> Self {
+    ProfileError::Io(e)
+  }
+}
+
+impl fmt::Display for ProfileError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ProfileError::Io(e) => write!(f, "{}", e),
+      ProfileError::Corrupt(path, e) => {
+        write!(f, "corrupt profile manifest in {}: {}", path.display(), e)
+      }
+      ProfileError::BadLabel(reason) | ProfileError::NoIdentity(reason) => write!(f, "{}", reason),
+    }
+  }
+}
+
+impl ProfileError {
+  // `io_code` is the code of I/O errors, ERR_KEYFILE_READ or ERR_KEYFILE_WRITE
+  // depending on whether the call changes the directory. The label or id is
+  // always the 2nd argument
+  fn coded(&self, dir: &Path, io_code: &'static str) -> CodedError {
+    match self {
+      ProfileError::Io(_) => {
+        CodedError::new(io_code, self.to_string()).field("path", dir.to_string_lossy())
+      }
+      ProfileError::Corrupt(path, _) => CodedError::new(errors::KEYFILE_CORRUPT, self.to_string())
+        .field("path", path.to_string_lossy()),
+      ProfileError::BadLabel(_) | ProfileError::NoIdentity(_) => {
+        CodedError::range_error(errors::INVALID_ARG_VALUE, self.to_string()).arg(1)
+      }
+    }
+  }
+}
+
+fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+fn read_profiles(dir: &Path) -> Result<Vec<Profile>, ProfileError> {
+  let entries = match fs::read_dir(dir) {
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+    result => result?,
+  };
+  let mut profiles = vec![];
+  for entry in entries {
+    let path = entry?.path();
+    let visible = path
+      .file_name()
+      .is_some_and(|name| !name.to_string_lossy().starts_with('.'));
+    if !visible || !path.is_dir() {
+      continue;
+    }
+    // A directory without a manifest is not a profile, or one whose
+    // creation didn't finish, so leave it alone
+    let json = match fs::read_to_string(path.join(MANIFEST_FILE)) {
+      Err(e) if e.kind() == ErrorKind::NotFound => continue,
+      result => result?,
+    };
+    let manifest = serde_json::from_str::<Manifest>(&json)
+      .map_err(|e| ProfileError::Corrupt(path.join(MANIFEST_FILE), e.to_string()))?;
+    profiles.push(Profile { manifest, path });
+  }
+  profiles.sort_by(|a, b| {
+    (a.manifest.created, &a.manifest.label).cmp(&(b.manifest.created, &b.manifest.label))
+  });
+  Ok(profiles)
+}
+
+fn read_default(dir: &Path) -> Result<Option<String>, Error> {
+  match fs::read_to_string(dir.join(DEFAULT_FILE)) {
+    Ok(id) => Ok(Some(id.trim().to_string())),
+    Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+fn write_default(dir: &Path, id: Option<&str>) -> Result<(), Error> {
+  let path = dir.join(DEFAULT_FILE);
+  match id {
+    Some(id) => replace_keyfile(&path, format!("{}\n", id).as_bytes()),
+    None => match fs::remove_file(&path) {
+      Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+      _ => Ok(()),
+    },
+  }
+}
+
+fn not_found(dir: &Path, id: &str) -> ProfileError {
+  ProfileError::NoIdentity(format!("no identity {} in {}", id, dir.display()))
+}
+
+fn internal_add(dir: &Path, label: &str) -> Result<Profile, ProfileError> {
+  if label.is_empty() {
+    return Err(ProfileError::BadLabel("label cannot be empty".to_string()));
+  }
+  let _lock = KeyfileLock::acquire(&dir.join(LOCK_NAME))?;
+  if read_profiles(dir)?
+    .iter()
+    .any(|p| p.manifest.label == label)
+  {
+    return Err(ProfileError::BadLabel(format!(
+      "an identity labelled \"{}\" already exists",
+      label
+    )));
+  }
+
+  let keypair = Keypair::generate();
+  let name = base64::encode_config(keypair.public.0, base64::URL_SAFE_NO_PAD);
+  let path = dir.join(name);
+  write_keyfile(
+    &path.join("secret"),
+    keyfile_string(&keypair, false).as_bytes(),
+  )?;
+
+  let manifest = Manifest {
+    id: keypair.public.as_base64().wrap('@', ".ed25519"),
+    label: label.to_string(),
+    created: now_millis(),
+  };
+  let json = serde_json::to_string_pretty(&manifest).unwrap(); // infallible
+  replace_keyfile(&path.join(MANIFEST_FILE), json.as_bytes())?;
+
+  // The first identity becomes the default
+  if read_default(dir)?.is_none() {
+    write_default(dir, Some(&manifest.id))?;
+  }
+  Ok(Profile { manifest, path })
+}
+
+// The keyfile is moved to `.removed` instead of being deleted, because
+// losing a secret key cannot be undone
+fn internal_remove(dir: &Path, id: &str) -> Result<bool, ProfileError> {
+  let _lock = KeyfileLock::acquire(&dir.join(LOCK_NAME))?;
+  let profile = match read_profiles(dir)?
+    .into_iter()
+    .find(|p| p.manifest.id == id)
+  {
+    Some(profile) => profile,
+    None => return Ok(false),
+  };
+
+  let removed_dir = dir.join(REMOVED_DIR);
+  fs::create_dir_all(&removed_dir)?;
+  let mut name = profile.path.file_name().unwrap_or_default().to_os_string();
+  name.push(format!("-{}", now_millis()));
+  fs::rename(&profile.path, removed_dir.join(name))?;
+
+  if read_default(dir)?.as_deref() == Some(id) {
+    write_default(dir, None)?;
+  }
+  Ok(true)
+}
+
+fn internal_get_default(dir: &Path) -> Result<Option<Profile>, ProfileError> {
+  let id = match read_default(dir)? {
+    Some(id) => id,
+    None => return Ok(None),
+  };
+  Ok(
+    read_profiles(dir)?
+      .into_iter()
+      .find(|p| p.manifest.id == id),
+  )
+}
+
+fn internal_set_default(dir: &Path, id: &str) -> Result<Profile, ProfileError> {
+  let _lock = KeyfileLock::acquire(&dir.join(LOCK_NAME))?;
+  let profile = read_profiles(dir)?
+    .into_iter()
+    .find(|p| p.manifest.id == id)
+    .ok_or_else(|| not_found(dir, id))?;
+  write_default(dir, Some(id))?;
+  Ok(profile)
+}
+
+fn profile_to_js<'a>(
+  cx: &mut impl Context<'a>,
+  profile: &Profile,
+  default_id: Option<&str>,
+) -> JsResult<'a, JsObject> {
+  let obj = cx.empty_object();
+  let id = cx.string(&profile.manifest.id);
+  let label = cx.string(&profile.manifest.label);
+  let path = cx.string(profile.path.to_string_lossy());
+  let created = cx.number(profile.manifest.created as f64);
+  let is_default = cx.boolean(default_id == Some(profile.manifest.id.as_str()));
+  obj.set(cx, "id", id)?;
+  obj.set(cx, "label", label)?;
+  obj.set(cx, "path", path)?;
+  obj.set(cx, "created", created)?;
+  obj.set(cx, "isDefault", is_default)?;
+  Ok(obj)
+}
+
+// listIdentities: (dir: string) => Array<{id, label, path, created, isDefault}>
+pub fn neon_list_identities(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let dir = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the only argument to `listIdentities`",
+    )?
+    .value();
+  let dir = Path::new(&dir);
+
+  let profiles = read_profiles(dir)
+    .and_then(|profiles| Ok((profiles, read_default(dir)?)))
+    .or_else(|e| e.coded(dir, errors::KEYFILE_READ).throw(&mut cx));
+  let (profiles, default_id) = profiles?;
+
+  let arr = JsArray::new(&mut cx, profiles.len() as u32);
+  for (i, profile) in profiles.iter().enumerate() {
+    let obj = profile_to_js(&mut cx, profile, default_id.as_deref())?;
+    arr.set(&mut cx, i as u32, obj)?;
+  }
+  Ok(arr)
+}
+
+// addIdentity: (dir: string, label: string) => {id, label, path, created, isDefault}
+pub fn neon_add_identity(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let dir = cx
+    .arg_as::<JsString>(0, "expected string as the first argument to `addIdentity`")?
+    .value();
+  let label = cx
+    .arg_as::<JsString>(
+      1,
+      "expected label string as the 2nd argument to `addIdentity`",
+    )?
+    .value();
+  let dir = Path::new(&dir);
+
+  let added = internal_add(dir, &label)
+    .and_then(|profile| Ok((profile, read_default(dir)?)))
+    .or_else(|e| e.coded(dir, errors::KEYFILE_WRITE).throw(&mut cx));
+  let (profile, default_id) = added?;
+
+  profile_to_js(&mut cx, &profile, default_id.as_deref())
+}
+
+// removeIdentity: (dir: string, id: string) => boolean
+pub fn neon_remove_identity(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let dir = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `removeIdentity`",
+    )?
+    .value();
+  let id = cx
+    .arg_as::<JsString>(
+      1,
+      "expected id string as the 2nd argument to `removeIdentity`",
+    )?
+    .value();
+
+  let dir = Path::new(&dir);
+  let removed =
+    internal_remove(dir, &id).or_else(|e| e.coded(dir, errors::KEYFILE_WRITE).throw(&mut cx))?;
+
+  Ok(cx.boolean(removed))
+}
+
+// getDefault: (dir: string) => {id, label, path, created, isDefault} | null
+pub fn neon_get_default(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let dir = cx
+    .arg_as::<JsString>(0, "expected string as the only argument to `getDefault`")?
+    .value();
+
+  let dir = Path::new(&dir);
+  match internal_get_default(dir).or_else(|e| e.coded(dir, errors::KEYFILE_READ).throw(&mut cx))? {
+    Some(profile) => {
+      let id = profile.manifest.id.clone();
+      Ok(profile_to_js(&mut cx, &profile, Some(&id))?.upcast())
+    }
+    None => Ok(cx.null().upcast()),
+  }
+}
+
+// setDefault: (dir: string, id: string) => {id, label, path, created, isDefault}
+pub fn neon_set_default(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let dir = cx
+    .arg_as::<JsString>(0, "expected string as the first argument to `setDefault`")?
+    .value();
+  let id = cx
+    .arg_as::<JsString>(1, "expected id string as the 2nd argument to `setDefault`")?
+    .value();
+
+  let dir = Path::new(&dir);
+  let profile = internal_set_default(dir, &id)
+    .or_else(|e| e.coded(dir, errors::KEYFILE_WRITE).throw(&mut cx))?;
+
+  profile_to_js(&mut cx, &profile, Some(&id))
+}
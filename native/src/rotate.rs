@@ -0,0 +1,247 @@
+use super::atomic::{link_keyfile, rename_temp, write_temp, KeyfileLock};
+use super::errors::{self, CodedError};
+use super::load_create::{
+  internal_load, keyfile_path, keyfile_string, load_error, write_error_code,
+};
+use super::utils::{self, get_field_opt, make_keys_obj, ContextExt, HandleExt, StringExt};
+use arrayvec::ArrayVec;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use ssb_crypto::{Keypair, PublicKey, Signature};
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ROTATION_TYPE: &str = "ssb-identity-rotation";
+
+// The signed part of a rotation statement. Its JSON serialization, with the
+// fields in this order, is what the new key signs
+#[derive(Serialize, Deserialize)]
+struct Rotation {
+  #[serde(rename = "type")]
+  kind: String,
+  old: String,
+  new: String,
+  timestamp: u64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Statement {
+  #[serde(flatten)]
+  rotation: Rotation,
+  // By the new key, over the rotation
+  signature: String,
+  // By the old key, over the rotation followed by `signature`
+  countersignature: String,
+}
+
+struct Rotated {
+  keypair: Keypair,
+  statement: Statement,
+  archive: PathBuf,
+}
+
+fn feed_id(keypair: &Keypair) -> String {
+  keypair.public.as_base64().wrap('@', ".ed25519")
+}
+
+fn countersigned_bytes(rotation_bytes: &[u8], signature: &str) -> Vec<u8> {
+  [rotation_bytes, signature.as_bytes()].concat()
+}
+
+fn sign_rotation(old: &Keypair, new: &Keypair, reason: Option<String>) -> Statement {
+  let rotation = Rotation {
+    kind: ROTATION_TYPE.to_string(),
+    old: feed_id(old),
+    new: feed_id(new),
+    timestamp: SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_millis() as u64)
+      .unwrap_or(0),
+    reason,
+  };
+  let rotation_bytes = serde_json::to_vec(&rotation).unwrap(); // infallible
+  let signature = new
+    .sign(&rotation_bytes)
+    .as_base64()
+    .with_suffix(".sig.ed25519");
+  let countersignature = old
+    .sign(&countersigned_bytes(&rotation_bytes, &signature))
+    .as_base64()
+    .with_suffix(".sig.ed25519");
+  Statement {
+    rotation,
+    signature,
+    countersignature,
+  }
+}
+
+fn parse_feed_id(id: &str) -> Option<PublicKey> {
+  let b64 = id.strip_prefix('@')?.strip_suffix(".ed25519")?;
+  PublicKey::from_base64(b64)
+}
+
+fn parse_signature(sig: &str) -> Option<Signature> {
+  Signature::from_base64(sig.strip_suffix(".sig.ed25519")?)
+}
+
+// Errors are about the statement, the only argument of `verifyRotation`
+fn verify_statement(statement: &Statement) -> Result<bool, CodedError> {
+  let rotation = &statement.rotation;
+  if rotation.kind != ROTATION_TYPE {
+    return Err(
+      CodedError::range_error(
+        errors::INVALID_ARG_VALUE,
+        format!("statement type must be \"{}\"", ROTATION_TYPE),
+      )
+      .arg(0),
+    );
+  }
+  let bad = |code, msg| CodedError::new(code, msg).arg(0);
+  let old = parse_feed_id(&rotation.old).ok_or_else(|| {
+    bad(
+      errors::BAD_PUBLIC_KEY,
+      "statement has an invalid `old` feed id",
+    )
+  })?;
+  let new = parse_feed_id(&rotation.new).ok_or_else(|| {
+    bad(
+      errors::BAD_PUBLIC_KEY,
+      "statement has an invalid `new` feed id",
+    )
+  })?;
+  let signature = parse_signature(&statement.signature).ok_or_else(|| {
+    bad(
+      errors::BAD_SIGNATURE,
+      "statement has an invalid `signature`",
+    )
+  })?;
+  let countersignature = parse_signature(&statement.countersignature).ok_or_else(|| {
+    bad(
+      errors::BAD_SIGNATURE,
+      "statement has an invalid `countersignature`",
+    )
+  })?;
+
+  let rotation_bytes = serde_json::to_vec(rotation).unwrap(); // infallible
+  Ok(
+    new.verify(&signature, &rotation_bytes)
+      && old.verify(
+        &countersignature,
+        &countersigned_bytes(&rotation_bytes, &statement.signature),
+      ),
+  )
+}
+
+fn archive_path(path: &Path) -> PathBuf {
+  let millis = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or(0);
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(format!(".rotated-{}", millis));
+  path.with_file_name(name)
+}
+
+// Writes the new keyfile to a temp file, links the old keyfile to
+// `<name>.rotated-<timestamp>`, and only then renames the new one into place.
+// Whenever the process dies, the old keypair is still on disk
+fn internal_rotate(path_str: &str, reason: Option<String>) -> Result<Rotated, CodedError> {
+  let path = keyfile_path(path_str);
+  let write_error =
+    |e: io::Error| CodedError::new(write_error_code(&e), e.to_string()).field("path", path_str);
+  let _lock = KeyfileLock::acquire(&path).map_err(write_error)?;
+  let old = internal_load(&path).map_err(|e| load_error(&e, path_str))?;
+
+  let new = Keypair::generate();
+  let contents = keyfile_string(&new, false);
+  let tmp_path = write_temp(&path, contents.as_bytes()).map_err(write_error)?;
+  let archive = archive_path(&path);
+  if let Err(e) = link_keyfile(&path, &archive) {
+    let _ = fs::remove_file(&tmp_path);
+    return Err(match e.kind() {
+      ErrorKind::AlreadyExists => CodedError::new(
+        errors::KEYFILE_EXISTS,
+        format!("cannot archive keyfile to {}, it exists", archive.display()),
+      )
+      .field("path", archive.to_string_lossy()),
+      _ => write_error(e),
+    });
+  }
+  if let Err(e) = rename_temp(&tmp_path, &path) {
+    let _ = fs::remove_file(&archive);
+    return Err(write_error(e));
+  }
+
+  let statement = sign_rotation(&old, &new, reason);
+  Ok(Rotated {
+    keypair: new,
+    statement,
+    archive,
+  })
+}
+
+// rotateIdentity: (path: string, opts?: {reason?: string}) => {keys, statement, archive}
+pub fn neon_rotate_identity(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let path = cx
+    .arg_as::<JsString>(
+      0,
+      "expected string as the first argument to `rotateIdentity`",
+    )?
+    .value();
+  let reason = match cx
+    .argument_opt(1)
+    .and_then(|v| v.try_downcast::<JsObject>())
+  {
+    Some(opts) => {
+      get_field_opt::<JsString>(&mut cx, opts, 1, "reason", "opts.reason must be a string")?
+        .map(|s| s.value())
+    }
+    None => None,
+  };
+
+  let rotated = internal_rotate(&path, reason).or_else(|e| e.throw(&mut cx))?;
+
+  let result = cx.empty_object();
+  let keys = make_keys_obj(&mut cx, &rotated.keypair)?;
+  let json = cx.string(serde_json::to_string(&rotated.statement).unwrap()); // infallible
+  let statement = utils::json_parse(&mut cx, json)?;
+  let archive = cx.string(rotated.archive.to_string_lossy());
+  result.set(&mut cx, "keys", keys)?;
+  result.set(&mut cx, "statement", statement)?;
+  result.set(&mut cx, "archive", archive)?;
+  Ok(result)
+}
+
+// verifyRotation: (statement: obj | string) => boolean
+pub fn neon_verify_rotation(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let arg = cx.argument::<JsValue>(0)?;
+  let json = if let Some(s) = arg.try_downcast::<JsString>() {
+    s.value()
+  } else if arg.is_a::<JsObject>() {
+    utils::json_stringify(&mut cx, ArrayVec::from([arg]))?.value()
+  } else {
+    return CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected the rotation statement as the argument to `verifyRotation`",
+    )
+    .arg(0)
+    .throw(&mut cx);
+  };
+
+  let statement = serde_json::from_str::<Statement>(&json).or_else(|e| {
+    CodedError::type_error(
+      errors::BAD_ENCODING,
+      format!("malformed rotation statement: {}", e),
+    )
+    .arg(0)
+    .throw(&mut cx)
+  })?;
+  let passed = verify_statement(&statement).or_else(|e| e.throw(&mut cx))?;
+
+  Ok(cx.boolean(passed))
+}
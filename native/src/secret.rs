@@ -1,64 +1,148 @@
-use super::utils::{self, ContextExt, OptionExt};
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::pool;
+use super::utils::{self, ContextExt};
 use arrayvec::ArrayVec;
 use neon::prelude::*;
 use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use zeroize::Zeroizing;
 
-pub fn neon_secret_box(mut cx: FunctionContext) -> JsResult<JsValue> {
+// The plaintext or cyphertext, with the key and nonce to use on it
+type Sealing = (Zeroizing<Vec<u8>>, Key, Nonce);
+type Opening = (Vec<u8>, Key, Nonce);
+
+// The key and nonce, both taken from the key buffer at index 1
+fn key_arg(cx: &mut FunctionContext) -> NeonResult<(Key, Nonce)> {
+  let js_key = cx.arg_as::<JsBuffer>(1, "2nd argument must be the key as a buffer")?;
+
+  let key_bytes = cx.borrow(&js_key, |bytes| {
+    Zeroizing::new(bytes.as_slice::<u8>().to_vec())
+  });
+  let key = key_bytes
+    .get(0..32)
+    .and_then(Key::from_slice)
+    .or_throw_code(
+      cx,
+      CodedError::range_error(
+        errors::BAD_SECRETBOX_KEY,
+        "expected `secretbox` key to be at least 32 bytes",
+      )
+      .arg(1),
+    )?;
+  let nonce = Nonce::from_slice(&key_bytes[0..24]).unwrap(); // infallible
+  Ok((key, nonce))
+}
+
+// None when there is nothing to box, which makes `secretBox` return undefined
+fn secret_box_args(cx: &mut FunctionContext) -> NeonResult<Option<Sealing>> {
   let arg1 = cx.argument::<JsValue>(0)?;
 
   if arg1.is_a::<JsUndefined>() || arg1.is_a::<JsNull>() {
-    return Ok(cx.undefined().upcast());
+    return Ok(None);
   }
 
-  let mut plaintext = utils::json_stringify(&mut cx, ArrayVec::from([arg1]))?
-    .value()
-    .into_bytes();
+  let plaintext = Zeroizing::new(
+    utils::json_stringify(cx, ArrayVec::from([arg1]))?
+      .value()
+      .into_bytes(),
+  );
 
-  let js_key = cx.arg_as::<JsBuffer>(1, "2nd argument must be the key as a buffer")?;
+  let (key, nonce) = key_arg(cx)?;
+  Ok(Some((plaintext, key, nonce)))
+}
 
-  let key_bytes = cx.borrow(&js_key, |bytes| bytes.as_slice::<u8>());
-  let key = Key::from_slice(&key_bytes[0..32])
-    .or_throw(&mut cx, "expected `secretbox` key to be at least 32 bytes")?;
-  let nonce = Nonce::from_slice(&key_bytes[0..24]).unwrap(); // infallible
+fn seal(args: Option<Sealing>) -> Option<Vec<u8>> {
+  let (plaintext, key, nonce) = args?;
+  // "Attached" format begins with the hmac
+  let mut cyphertext = vec![0; Hmac::SIZE + plaintext.len()];
+  key.seal_attached_into(&plaintext, &nonce, &mut cyphertext);
+  Some(cyphertext)
+}
 
-  let hmac = key.seal(&mut plaintext, &nonce);
-  // `plaintext` now contains the cyphertext. "Attached" format begins with the hmac:
-  plaintext.splice(0..0, hmac.0.iter().cloned());
-  let buffer = utils::bytes_to_buffer(&mut cx, &plaintext)?;
-  Ok(buffer.upcast())
+fn sealed_to_buffer<'a>(
+  cx: &mut impl Context<'a>,
+  cyphertext: Option<Vec<u8>>,
+) -> JsResult<'a, JsValue> {
+  match cyphertext {
+    None => Ok(cx.undefined().upcast()),
+    Some(cyphertext) => Ok(utils::bytes_to_buffer(cx, &cyphertext)?.upcast()),
+  }
 }
 
-pub fn neon_secret_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
+pub fn neon_secret_box(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let args = secret_box_args(&mut cx)?;
+  let cyphertext = seal(args);
+  sealed_to_buffer(&mut cx, cyphertext)
+}
+
+// secretBoxAsync: (obj: any, key: Buffer, cb: (err, Buffer) => void) => void
+pub fn neon_secret_box_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "secretBoxAsync")?;
+  let args = secret_box_args(&mut cx)?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || seal(args),
+    |cx, _, cyphertext| sealed_to_buffer(cx, cyphertext),
+  );
+  Ok(cx.undefined())
+}
+
+// None when the 1st argument isn't a buffer, which makes `secretUnbox` return
+// undefined
+fn secret_unbox_args(cx: &mut FunctionContext) -> NeonResult<Option<Opening>> {
   let arg1 = cx.argument::<JsValue>(0)?;
 
   if !arg1.is_a::<JsBuffer>() {
-    return Ok(cx.undefined().upcast());
+    return Ok(None);
   }
 
-  let buffer = arg1.downcast::<JsBuffer>().or_throw(&mut cx)?;
-  let cyphertext = cx.borrow(&buffer, |bytes| bytes.as_slice::<u8>());
+  let buffer = arg1.downcast::<JsBuffer>().or_throw(cx)?;
+  let cyphertext = cx.borrow(&buffer, |bytes| bytes.as_slice::<u8>().to_vec());
 
-  let js_key = cx.arg_as::<JsBuffer>(1, "2nd argument must be the key as a buffer")?;
-
-  let key_bytes = cx.borrow(&js_key, |bytes| bytes.as_slice::<u8>());
-  if key_bytes.len() < 32 {
-    cx.throw_error("expected `secretbox` key to be at least 32 bytes")?;
-  }
-  let key = Key::from_slice(&key_bytes[0..32]).unwrap(); // infallible
-  let nonce = Nonce::from_slice(&key_bytes[0..24]).unwrap(); // infallible
+  let (key, nonce) = key_arg(cx)?;
+  Ok(Some((cyphertext, key, nonce)))
+}
 
-  let mut plaintext = vec![0; cyphertext.len() - Hmac::SIZE];
+// Err if the cyphertext doesn't decrypt with the key
+fn open(args: Option<Opening>) -> Result<Option<Zeroizing<Vec<u8>>>, ()> {
+  let (cyphertext, key, nonce) = match args {
+    Some(args) => args,
+    None => return Ok(None),
+  };
+  // Too short to even hold the hmac
+  let len = cyphertext.len().checked_sub(Hmac::SIZE).ok_or(())?;
+  let mut plaintext = Zeroizing::new(vec![0; len]);
 
-  if !key.open_attached_into(cyphertext, &nonce, &mut plaintext) {
-    return cx.throw_error("failed to decrypt in secretUnbox");
+  if !key.open_attached_into(&cyphertext, &nonce, &mut plaintext) {
+    return Err(());
   }
-  let plaintext_str = String::from_utf8(plaintext);
+  Ok(Some(plaintext))
+}
+
+fn opened_to_js<'a>(
+  cx: &mut impl Context<'a>,
+  plaintext: Result<Option<Zeroizing<Vec<u8>>>, ()>,
+) -> JsResult<'a, JsValue> {
+  let plaintext = match plaintext {
+    Ok(Some(plaintext)) => plaintext,
+    Ok(None) => return Ok(cx.undefined().upcast()),
+    Err(()) => {
+      return throw_code(
+        cx,
+        errors::DECRYPT_FAILED,
+        "failed to decrypt in secretUnbox",
+      )
+    }
+  };
+  let plaintext_str = std::str::from_utf8(&plaintext);
   if plaintext_str.is_err() {
     return Ok(cx.undefined().upcast());
   }
   let plaintext_str = cx.string(plaintext_str.unwrap());
 
-  let out = utils::json_parse(&mut cx, plaintext_str);
+  let out = utils::json_parse(cx, plaintext_str);
   if out.is_err() {
     return Ok(cx.undefined().upcast());
   }
@@ -66,3 +150,24 @@ pub fn neon_secret_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
 
   Ok(out.upcast())
 }
+
+pub fn neon_secret_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let args = secret_unbox_args(&mut cx)?;
+  let plaintext = open(args);
+  opened_to_js(&mut cx, plaintext)
+}
+
+// secretUnboxAsync: (boxed: Buffer, key: Buffer, cb: (err, any) => void) => void
+pub fn neon_secret_unbox_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "secretUnboxAsync")?;
+  let args = secret_unbox_args(&mut cx)?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || open(args),
+    |cx, _, plaintext| opened_to_js(cx, plaintext),
+  );
+  Ok(cx.undefined())
+}
@@ -0,0 +1,304 @@
+use neon::prelude::*;
+use zeroize::Zeroize;
+
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+// Opt-in storage for secrets that the module keeps for a long time, like the
+// keypair of an identity handle. When enabled, each secret gets pages of its
+// own, locked in RAM so they are never swapped out, left out of core dumps,
+// and surrounded by inaccessible guard pages
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOCKED_SECRETS: AtomicUsize = AtomicUsize::new(0);
+static LOCKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static UNLOCKED_SECRETS: AtomicUsize = AtomicUsize::new(0);
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+// Whether a page could be locked, tried once when first asked
+#[cfg(unix)]
+static LOCK_PROBE: OnceLock<bool> = OnceLock::new();
+
+fn record_error(what: &str, e: std::io::Error) {
+  if let Ok(mut last) = LAST_ERROR.lock() {
+    *last = Some(format!("{} failed: {}", what, e));
+  }
+}
+
+// Moves `value` to `dst`, then wipes the bytes it was passed in, which a
+// plain move would leave behind
+unsafe fn move_wiped<T>(value: T, dst: *mut T) {
+  let mut value = ManuallyDrop::new(value);
+  let src = &mut *value as *mut T;
+  ptr::copy_nonoverlapping(src, dst, 1);
+  std::slice::from_raw_parts_mut(src as *mut u8, mem::size_of::<T>()).zeroize();
+}
+
+fn boxed_wiped<T>(value: T) -> Box<T> {
+  let mut slot = Box::new(MaybeUninit::<T>::uninit());
+  unsafe {
+    move_wiped(value, slot.as_mut_ptr());
+    Box::from_raw(Box::into_raw(slot) as *mut T)
+  }
+}
+
+pub struct Secret<T>(Storage<T>);
+
+enum Storage<T> {
+  Heap(Box<T>),
+  #[cfg(unix)]
+  Pages(pages::Pages<T>),
+  // On the heap because mapping pages for it failed
+  #[cfg(unix)]
+  Unlocked(Box<T>),
+}
+
+impl<T> Secret<T> {
+  // Falls back to the heap when secure memory is off, unsupported, or the
+  // pages can't be mapped, which `secureMemoryStatus` then reports
+  pub fn new(value: T) -> Self {
+    #[cfg(unix)]
+    {
+      if ENABLED.load(Ordering::SeqCst) {
+        match pages::Region::alloc(std::mem::size_of::<T>()) {
+          Ok(region) => return Secret(Storage::Pages(pages::Pages::new(region, value))),
+          Err(e) => {
+            record_error("mmap", e);
+            UNLOCKED_SECRETS.fetch_add(1, Ordering::SeqCst);
+            return Secret(Storage::Unlocked(boxed_wiped(value)));
+          }
+        }
+      }
+    }
+    Secret(Storage::Heap(boxed_wiped(value)))
+  }
+}
+
+impl<T> Deref for Secret<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    match &self.0 {
+      Storage::Heap(value) => value,
+      #[cfg(unix)]
+      Storage::Pages(pages) => pages,
+      #[cfg(unix)]
+      Storage::Unlocked(value) => value,
+    }
+  }
+}
+
+impl<T> Drop for Secret<T> {
+  fn drop(&mut self) {
+    #[cfg(unix)]
+    if let Storage::Unlocked(_) = self.0 {
+      UNLOCKED_SECRETS.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+}
+
+#[cfg(unix)]
+mod pages {
+  use super::{move_wiped, record_error, LOCKED_BYTES, LOCKED_SECRETS, UNLOCKED_SECRETS};
+  use zeroize::Zeroize;
+
+  use std::io;
+  use std::ops::Deref;
+  use std::ptr::{self, NonNull};
+  use std::sync::atomic::Ordering;
+
+  // A guard page, then `len` bytes of data pages, then another guard page
+  pub struct Region {
+    base: *mut u8,
+    page: usize,
+    len: usize,
+    writable: bool,
+    locked: bool,
+  }
+
+  impl Region {
+    pub fn alloc(size: usize) -> io::Result<Region> {
+      unsafe {
+        let page = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let len = size.max(1).div_ceil(page) * page;
+        let base = libc::mmap(
+          ptr::null_mut(),
+          len + 2 * page,
+          libc::PROT_NONE,
+          libc::MAP_PRIVATE | libc::MAP_ANON,
+          -1,
+          0,
+        );
+        if base == libc::MAP_FAILED {
+          return Err(io::Error::last_os_error());
+        }
+        // From here on, dropping the region unmaps it
+        let mut region = Region {
+          base: base as *mut u8,
+          page,
+          len,
+          writable: false,
+          locked: false,
+        };
+        if libc::mprotect(region.data(), len, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+          return Err(io::Error::last_os_error());
+        }
+        region.writable = true;
+        // Other platforms have no way to leave pages out of core dumps
+        #[cfg(target_os = "linux")]
+        libc::madvise(region.data(), len, libc::MADV_DONTDUMP);
+
+        // Fails when RLIMIT_MEMLOCK is too low, but the pages are still usable
+        if libc::mlock(region.data(), len) == 0 {
+          region.locked = true;
+          LOCKED_SECRETS.fetch_add(1, Ordering::SeqCst);
+          LOCKED_BYTES.fetch_add(len, Ordering::SeqCst);
+        } else {
+          record_error("mlock", io::Error::last_os_error());
+          UNLOCKED_SECRETS.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(region)
+      }
+    }
+
+    pub fn is_locked(&self) -> bool {
+      self.locked
+    }
+
+    fn data(&self) -> *mut libc::c_void {
+      unsafe { self.base.add(self.page) as *mut libc::c_void }
+    }
+  }
+
+  impl Drop for Region {
+    fn drop(&mut self) {
+      unsafe {
+        if self.writable {
+          std::slice::from_raw_parts_mut(self.data() as *mut u8, self.len).zeroize();
+          if self.locked {
+            libc::munlock(self.data(), self.len);
+            LOCKED_SECRETS.fetch_sub(1, Ordering::SeqCst);
+            LOCKED_BYTES.fetch_sub(self.len, Ordering::SeqCst);
+          } else {
+            UNLOCKED_SECRETS.fetch_sub(1, Ordering::SeqCst);
+          }
+        }
+        libc::munmap(self.base as *mut libc::c_void, self.len + 2 * self.page);
+      }
+    }
+  }
+
+  // A value living in a region of its own, which is page aligned
+  pub struct Pages<T> {
+    value: NonNull<T>,
+    _region: Region,
+  }
+
+  // The pages are owned by this value alone, like a Box
+  unsafe impl<T: Send> Send for Pages<T> {}
+  unsafe impl<T: Sync> Sync for Pages<T> {}
+
+  impl<T> Pages<T> {
+    pub fn new(region: Region, value: T) -> Self {
+      let ptr = region.data() as *mut T;
+      unsafe { move_wiped(value, ptr) };
+      Pages {
+        value: NonNull::new(ptr).unwrap(), // mmap never returns null
+        _region: region,
+      }
+    }
+  }
+
+  impl<T> Deref for Pages<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+      unsafe { self.value.as_ref() }
+    }
+  }
+
+  impl<T> Drop for Pages<T> {
+    fn drop(&mut self) {
+      // Then the region wipes and unmaps the pages
+      unsafe { ptr::drop_in_place(self.value.as_ptr()) };
+    }
+  }
+}
+
+// Whether locking works, tried on a page of its own the first time only, so
+// that asking for the status doesn't map pages or replace `error` each time
+#[cfg(unix)]
+fn probe_lock() -> bool {
+  *LOCK_PROBE.get_or_init(|| match pages::Region::alloc(1) {
+    Ok(region) => region.is_locked(),
+    Err(e) => {
+      record_error("mmap", e);
+      false
+    }
+  })
+}
+
+// The soft RLIMIT_MEMLOCK in bytes, which may be Infinity
+#[cfg(unix)]
+fn memlock_limit() -> f64 {
+  let mut limit = libc::rlimit {
+    rlim_cur: 0,
+    rlim_max: 0,
+  };
+  if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0
+    || limit.rlim_cur == libc::RLIM_INFINITY
+  {
+    return f64::INFINITY;
+  }
+  limit.rlim_cur as f64
+}
+
+fn status<'a>(cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+  let locked_secrets = LOCKED_SECRETS.load(Ordering::SeqCst);
+  let unlocked_secrets = UNLOCKED_SECRETS.load(Ordering::SeqCst);
+  // With no secrets in secure memory yet, say whether they would be locked
+  #[cfg(unix)]
+  let (supported, locked, limit) = (
+    true,
+    unlocked_secrets == 0 && (locked_secrets > 0 || probe_lock()),
+    Some(memlock_limit()),
+  );
+  #[cfg(not(unix))]
+  let (supported, locked, limit) = (false, false, None);
+
+  let obj = cx.empty_object();
+  let enabled = cx.boolean(ENABLED.load(Ordering::SeqCst));
+  let supported = cx.boolean(supported);
+  let locked = cx.boolean(locked);
+  let locked_bytes = cx.number(LOCKED_BYTES.load(Ordering::SeqCst) as f64);
+  let unlocked_secrets = cx.number(unlocked_secrets as f64);
+  obj.set(cx, "enabled", enabled)?;
+  obj.set(cx, "supported", supported)?;
+  obj.set(cx, "locked", locked)?;
+  obj.set(cx, "lockedBytes", locked_bytes)?;
+  obj.set(cx, "unlockedSecrets", unlocked_secrets)?;
+  if let Some(limit) = limit {
+    let limit = cx.number(limit);
+    obj.set(cx, "memlockLimit", limit)?;
+  }
+  let last_error = LAST_ERROR.lock().ok().and_then(|last| last.clone());
+  if let Some(e) = last_error {
+    let e = cx.string(e);
+    obj.set(cx, "error", e)?;
+  }
+  Ok(obj)
+}
+
+// enableSecureMemory: () => status, like `secureMemoryStatus`
+pub fn neon_enable_secure_memory(mut cx: FunctionContext) -> JsResult<JsObject> {
+  ENABLED.store(true, Ordering::SeqCst);
+  status(&mut cx)
+}
+
+// secureMemoryStatus: () => {enabled, supported, locked, lockedBytes, unlockedSecrets, memlockLimit?, error?}
+pub fn neon_secure_memory_status(mut cx: FunctionContext) -> JsResult<JsObject> {
+  status(&mut cx)
+}
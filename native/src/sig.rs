@@ -1,45 +1,118 @@
-use super::utils::{
-  self, get_string_or_field, type_name, ContextExt, HandleExt, OptionExt, StringExt, ValueExt,
-};
-use arrayvec::ArrayVec;
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::legacy_json;
+use super::pool;
+use super::utils::{self, type_name, ContextExt, HandleExt, StringExt, ValueExt};
 use neon::prelude::*;
 
 // TODO NetworkKey isn't a great name, I guess
 use ssb_crypto::{Keypair, NetworkKey as AuthKey, PublicKey, Signature};
 
-// sign: (keys: obj | string, hmac_key: Buffer | string, str: string) => string
-pub fn neon_sign(mut cx: FunctionContext) -> JsResult<JsString> {
-  // FIXME: detect `curve` from keys.curve or from u.getTag and validate it
-  let argc = cx.len();
-  if argc < 2 {
-    return cx.throw_error("sign requires at least two arguments: (keys, msg)");
+// Reads the hmac_key at `index`, given as a Buffer or a base64 string
+pub fn hmac_key_arg(cx: &mut FunctionContext, index: i32, ord: &str) -> NeonResult<AuthKey> {
+  let v = cx.argument::<JsValue>(index)?;
+  if let Some(buf) = v.try_downcast::<JsBuffer>() {
+    let bytes = cx.borrow(&buf, |data| data.as_slice::<u8>());
+    AuthKey::from_slice(bytes).or_throw_code(
+      cx,
+      CodedError::new(errors::BAD_HMAC_KEY, "hmac_key buffer must be 32 bytes").arg(index),
+    )
+  } else if let Some(s) = v.try_downcast::<JsString>() {
+    AuthKey::from_base64(&s.value()).or_throw_code(
+      cx,
+      CodedError::new(
+        errors::BAD_HMAC_KEY,
+        format!("expected {} argument to be a base64 string", ord),
+      )
+      .arg(index),
+    )
+  } else {
+    CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      format!("expected {} argument to be a Buffer for the hmac_key", ord),
+    )
+    .arg(index)
+    .throw(cx)
   }
+}
 
-  let keypair = {
-    let arg = cx.argument(0)?;
-    let private_str = get_string_or_field(&mut cx, arg, "private").or_throw(
-      &mut cx,
-      "expected 1st argument to be the keys object or the private key string",
-    )?;
+// A message to sign, read from the arguments on the main thread so that the
+// signing itself can run on any thread
+pub struct Signing {
+  keypair: Keypair,
+  hmac_key: Option<AuthKey>,
+  msg: Vec<u8>,
+}
 
-    Keypair::from_base64(&private_str).or_throw(&mut cx, "cannot decode private key bytes")?
+impl Signing {
+  pub fn sign(&self) -> String {
+    sign_bytes(&self.keypair, self.hmac_key.as_ref(), &self.msg)
+  }
+}
+
+// The `.sig.ed25519` signature of `msg`, or of its hmac tag when there is a
+// hmac_key
+pub fn sign_bytes(keypair: &Keypair, hmac_key: Option<&AuthKey>, msg: &[u8]) -> String {
+  let sig = match hmac_key {
+    None => keypair.sign(msg),
+    Some(hmac_key) => keypair.sign(&hmac_key.authenticate(msg).0),
   };
+  sig.as_base64().with_suffix(".sig.ed25519")
+}
+
+// Like `Signing`, for a signature to check
+pub struct Verifying {
+  public_key: PublicKey,
+  signature: Signature,
+  hmac_key: Option<AuthKey>,
+  msg: Vec<u8>,
+}
+
+impl Verifying {
+  pub fn verify(&self) -> bool {
+    verify_bytes(
+      &self.public_key,
+      &self.signature,
+      self.hmac_key.as_ref(),
+      &self.msg,
+    )
+  }
+}
+
+// Checks a signature of `msg`, or of its hmac tag when there is a hmac_key
+pub fn verify_bytes(
+  public_key: &PublicKey,
+  signature: &Signature,
+  hmac_key: Option<&AuthKey>,
+  msg: &[u8],
+) -> bool {
+  match hmac_key {
+    None => public_key.verify(signature, msg),
+    Some(hmac_key) => public_key.verify(signature, &hmac_key.authenticate(msg).0),
+  }
+}
+
+// The arguments of `sign` are the first `argc`, and any after them belong to
+// the async variant
+fn sign_args(cx: &mut FunctionContext, argc: i32, name: &str) -> NeonResult<Signing> {
+  // FIXME: detect `curve` from keys.curve or from u.getTag and validate it
+  if argc < 2 {
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!("{} requires at least two arguments: (keys, msg)", name),
+    );
+  }
+
+  let keypair = utils::keypair_arg(
+    cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
 
-  // TODO this is exactly the same inside neon_verify_obj, maybe could refactor
   let hmac_key = {
-    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(&mut cx) {
-      let authkey = cx.argument::<JsValue>(1).and_then(|v| {
-        if let Some(buf) = v.try_downcast::<JsBuffer>() {
-          let bytes = cx.borrow(&buf, |data| data.as_slice::<u8>());
-          AuthKey::from_slice(bytes).or_throw(&mut cx, "hmac_key buffer must be 32 bytes")
-        } else if let Some(s) = v.try_downcast::<JsString>() {
-          AuthKey::from_base64(&s.value())
-            .or_throw(&mut cx, "expected 2nd argument to be a base64 string")
-        } else {
-          cx.throw_error("expected 2nd argument to be a Buffer for the hmac_key")
-        }
-      })?;
-      Some(authkey)
+    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(cx) {
+      Some(hmac_key_arg(cx, 1, "2nd")?)
     } else {
       None
     }
@@ -50,246 +123,308 @@ pub fn neon_sign(mut cx: FunctionContext) -> JsResult<JsString> {
     .value()
     .into_bytes();
 
-  let sig = match hmac_key {
-    None => keypair.sign(msg.as_slice()),
-    Some(hmac_key) => {
-      let tag = hmac_key.authenticate(msg.as_slice());
-      keypair.sign(&tag.0)
-    }
-  };
+  Ok(Signing {
+    keypair,
+    hmac_key,
+    msg,
+  })
+}
 
-  let signature = cx.string(sig.as_base64().with_suffix(".sig.ed25519"));
+// sign: (keys: obj | string, hmac_key: Buffer | string, str: string) => string
+pub fn neon_sign(mut cx: FunctionContext) -> JsResult<JsString> {
+  let argc = cx.len();
+  let signing = sign_args(&mut cx, argc, "sign")?;
+  Ok(cx.string(signing.sign()))
+}
 
-  Ok(signature)
+// signAsync: (keys: obj | string, hmac_key: Buffer | string, str: string, cb: (err, string) => void) => void
+pub fn neon_sign_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "signAsync")?;
+  let argc = cx.len() - 1;
+  let signing = sign_args(&mut cx, argc, "signAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || signing.sign(),
+    |cx, _, sig| Ok(cx.string(sig).upcast()),
+  );
+  Ok(cx.undefined())
 }
 
-// verify: (keys: obj | string, signature: string, hmac_key, str: string) => boolean
-pub fn neon_verify(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+fn verify_args(cx: &mut FunctionContext, argc: i32, name: &str) -> NeonResult<Verifying> {
   // FIXME: detect `curve` from keys.curve or from u.getTag and validate it
-  let argc = cx.len();
   if argc < 3 {
-    return cx.throw_error("verify requires at least two arguments: (keys, msg)");
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!("{} requires at least two arguments: (keys, msg)", name),
+    );
   }
 
-  let public_key = {
-    let arg = cx.argument(0)?;
-    let public_str = get_string_or_field(&mut cx, arg, "public").or_throw(
-      &mut cx,
-      "expected `public` argument to be the keys object or the public key string",
-    )?;
-    PublicKey::from_base64(&public_str).or_throw(&mut cx, "cannot base64 decode the public key")?
-  };
+  let public_key = utils::public_key_arg(
+    cx,
+    0,
+    "expected `public` argument to be the keys object or the public key string",
+    "cannot base64 decode the public key",
+  )?;
 
   let signature = {
-    let mut sig = cx
-      .arg_as::<JsString>(1, "expected 2nd arg to be a signature string")?
+    let sig_index = 1;
+    let sig = cx
+      .arg_as::<JsString>(sig_index, "expected 2nd arg to be a signature string")?
       .value();
-    match sig.rfind(".sig.ed25519") {
-      None => return cx.throw_error("Invalid signature string, is missing dot suffix"),
-      Some(dot_index) => sig.truncate(dot_index)
-    };
-    Signature::from_base64(&sig).or_throw(&mut cx, "unable to decode signature base64 string")?
+    signature_from_str(cx, sig, sig_index)?
   };
 
-  // TODO this is almost the same inside neon_verify_obj, maybe could refactor
   let hmac_key = {
-    if argc == 4 && cx.argument::<JsValue>(2)?.is_truthy(&mut cx) {
-      let authkey = cx.argument::<JsValue>(2).and_then(|v| {
-        if let Some(buf) = v.try_downcast::<JsBuffer>() {
-          let bytes = cx.borrow(&buf, |data| data.as_slice::<u8>());
-          AuthKey::from_slice(bytes).or_throw(&mut cx, "hmac_key buffer must be 32 bytes")
-        } else if let Some(s) = v.try_downcast::<JsString>() {
-          AuthKey::from_base64(&s.value())
-            .or_throw(&mut cx, "expected 3rd argument to be a base64 string")
-        } else {
-          cx.throw_error("expected 3rd argument to be a Buffer for the hmac_key")
-        }
-      })?;
-      Some(authkey)
+    if argc == 4 && cx.argument::<JsValue>(2)?.is_truthy(cx) {
+      Some(hmac_key_arg(cx, 2, "3rd")?)
     } else {
       None
     }
   };
 
   let msg = cx
-    .arg_as::<JsString>(argc - 1 , "expected last arg to be a plaintext string")?
-    .value();
-
-  let passed = match hmac_key {
-    None => public_key.verify(&signature, msg.into_bytes().as_slice()),
-    Some(hmac_key) => {
-      let tag = hmac_key.authenticate(msg.into_bytes().as_slice());
-      public_key.verify(&signature, &tag.0)
+    .arg_as::<JsString>(argc - 1, "expected last arg to be a plaintext string")?
+    .value()
+    .into_bytes();
+
+  Ok(Verifying {
+    public_key,
+    signature,
+    hmac_key,
+    msg,
+  })
+}
+
+// Decodes a `.sig.ed25519` string, which came from the argument at `sig_index`
+fn signature_from_str(
+  cx: &mut FunctionContext,
+  mut sig: String,
+  sig_index: i32,
+) -> NeonResult<Signature> {
+  match sig.rfind(".sig.ed25519") {
+    None => {
+      return CodedError::new(
+        errors::SIG_SUFFIX,
+        "Invalid signature string, is missing dot suffix",
+      )
+      .arg(sig_index)
+      .throw(cx)
     }
+    Some(dot_index) => sig.truncate(dot_index),
   };
+  Signature::from_base64(&sig).or_throw_code(
+    cx,
+    CodedError::new(
+      errors::BAD_SIGNATURE,
+      "unable to decode signature base64 string",
+    )
+    .arg(sig_index),
+  )
+}
 
-  Ok(cx.boolean(passed))
+// verify: (keys: obj | string, signature: string, hmac_key, str: string) => boolean
+pub fn neon_verify(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let argc = cx.len();
+  let verifying = verify_args(&mut cx, argc, "verify")?;
+  Ok(cx.boolean(verifying.verify()))
 }
 
-// sign: (keys: obj | string, hmac_key?: string, o: obj) => string
-pub fn neon_sign_obj(mut cx: FunctionContext) -> JsResult<JsObject> {
+// verifyAsync: (keys: obj | string, signature: string, hmac_key, str: string, cb: (err, boolean) => void) => void
+pub fn neon_verify_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "verifyAsync")?;
+  let argc = cx.len() - 1;
+  let verifying = verify_args(&mut cx, argc, "verifyAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || verifying.verify(),
+    |cx, _, passed| Ok(cx.boolean(passed).upcast()),
+  );
+  Ok(cx.undefined())
+}
+
+// The object argument of `signObj` and `verifyObj`
+fn obj_arg<'a>(cx: &mut FunctionContext<'a>, argc: i32) -> JsResult<'a, JsObject> {
+  let (index, ord) = if argc == 2 { (1, "2nd") } else { (2, "3rd") };
+  let v = cx.argument::<JsValue>(index)?;
+  let obj = if v.is_a::<JsObject>() {
+    Ok(v.downcast::<JsObject>().unwrap())
+  } else {
+    CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      format!("expected {} arg to be object, was a {}", ord, type_name(&v)),
+    )
+    .arg(index)
+    .throw(cx)
+  }?;
+  Ok(obj)
+}
+
+// Returns the object to put the signature in too
+fn sign_obj_args<'a>(
+  cx: &mut FunctionContext<'a>,
+  argc: i32,
+  name: &str,
+) -> NeonResult<(Handle<'a, JsObject>, Signing)> {
   // FIXME: detect `curve` from keys.curve or from u.getTag and validate it
-  let argc = cx.len();
   if argc < 2 {
-    return cx.throw_error("signObj requires at least two arguments: (keys, msg)");
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!("{} requires at least two arguments: (keys, msg)", name),
+    );
   }
 
-  let keypair = {
-    let arg = cx.argument(0)?;
-    let private_str = get_string_or_field(&mut cx, arg, "private").or_throw(
-      &mut cx,
-      "expected 1st argument to be the keys object or the private key string",
-    )?;
+  let keypair = utils::keypair_arg(
+    cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot decode private key bytes",
+  )?;
 
-    Keypair::from_base64(&private_str).or_throw(&mut cx, "cannot decode private key bytes")?
-  };
-
-  // TODO this is exactly the same inside neon_verify_obj, maybe could refactor
   let hmac_key = {
-    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(&mut cx) {
-      let authkey = cx.argument::<JsValue>(1).and_then(|v| {
-        if let Some(buf) = v.try_downcast::<JsBuffer>() {
-          let bytes = cx.borrow(&buf, |data| data.as_slice::<u8>());
-          AuthKey::from_slice(bytes).or_throw(&mut cx, "hmac_key buffer must be 32 bytes")
-        } else if let Some(s) = v.try_downcast::<JsString>() {
-          AuthKey::from_base64(&s.value())
-            .or_throw(&mut cx, "expected 2nd argument to be a base64 string")
-        } else {
-          cx.throw_error("expected 2nd argument to be a Buffer for the hmac_key")
-        }
-      })?;
-      Some(authkey)
+    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(cx) {
+      Some(hmac_key_arg(cx, 1, "2nd")?)
     } else {
       None
     }
   };
 
-  // TODO this is exactly the same inside neon_verify_obj, maybe could refactor
-  let out_obj = {
-    let (index, ord) = if argc == 2 { (1, "2nd") } else { (2, "3rd") };
-    let v = cx.argument::<JsValue>(index)?;
-    let obj = if v.is_a::<JsObject>() {
-      Ok(v.downcast::<JsObject>().unwrap())
-    } else {
-      cx.throw_error(format!(
-        "expected {} arg to be object, was a {}",
-        ord,
-        type_name(&v)
-      ))
-    }?;
-    utils::clone_js_obj(&mut cx, obj)?
-  };
-
-  let msg = {
-    let null = cx.null();
-    let args = ArrayVec::from([out_obj.upcast(), null.upcast(), cx.number(2).upcast()]);
-    utils::json_stringify(&mut cx, args)?.value().into_bytes()
-  };
+  let obj = obj_arg(cx, argc)?;
+  let (out_obj, msg) = legacy_json::clone_and_stringify(cx, obj)?;
+  let msg = msg.into_bytes();
+
+  Ok((
+    out_obj,
+    Signing {
+      keypair,
+      hmac_key,
+      msg,
+    },
+  ))
+}
 
-  // TODO this is exactly the same inside neon_verify_obj, maybe could refactor
-  let sig = match hmac_key {
-    None => keypair.sign(msg.as_slice()),
-    Some(hmac_key) => {
-      let tag = hmac_key.authenticate(msg.as_slice());
-      keypair.sign(&tag.0)
-    }
-  };
-  let signature = cx.string(sig.as_base64().with_suffix(".sig.ed25519"));
+fn set_signature<'a>(
+  cx: &mut impl Context<'a>,
+  out_obj: Handle<'a, JsObject>,
+  sig: String,
+) -> JsResult<'a, JsObject> {
+  let signature = cx.string(sig);
+  out_obj.set(cx, "signature", signature).or_else(|_| {
+    throw_code(
+      cx,
+      errors::INTERNAL,
+      "failed to set the `signature` field in the object",
+    )
+  })?;
+  Ok(out_obj)
+}
 
-  out_obj
-    .set(&mut cx, "signature", signature)
-    .or_else(|_| cx.throw_error("failed to set the `signature` field in the object"))?;
+// sign: (keys: obj | string, hmac_key?: string, o: obj) => string
+pub fn neon_sign_obj(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let argc = cx.len();
+  let (out_obj, signing) = sign_obj_args(&mut cx, argc, "signObj")?;
+  set_signature(&mut cx, out_obj, signing.sign())
+}
 
-  Ok(out_obj)
+// signObjAsync: (keys: obj | string, hmac_key?: string, o: obj, cb: (err, obj) => void) => void
+pub fn neon_sign_obj_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "signObjAsync")?;
+  let argc = cx.len() - 1;
+  let (out_obj, signing) = sign_obj_args(&mut cx, argc, "signObjAsync")?;
+  // The object stays alive as `this` until the signature is put in it
+  pool::spawn(
+    &mut cx,
+    out_obj.upcast(),
+    callback,
+    move || signing.sign(),
+    |cx, out_obj, sig| {
+      let out_obj = out_obj.downcast::<JsObject>().or_throw(cx)?;
+      Ok(set_signature(cx, out_obj, sig)?.upcast())
+    },
+  );
+  Ok(cx.undefined())
 }
 
-// verify: (keys: obj | string, hmac_key?: string, o: obj) => boolean
-pub fn neon_verify_obj(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+fn verify_obj_args(cx: &mut FunctionContext, argc: i32, name: &str) -> NeonResult<Verifying> {
   // FIXME: detect `curve` from keys.curve or from u.getTag and validate it
-  let argc = cx.len();
   if argc < 2 {
-    return cx.throw_error("verifyObj requires at least two arguments: (keys, msg)");
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!("{} requires at least two arguments: (keys, msg)", name),
+    );
   }
 
-  let public_key = {
-    let arg = cx.argument(0)?;
-    let public_str = get_string_or_field(&mut cx, arg, "public").or_throw(
-      &mut cx,
-      "expected `public` argument to be the keys object or the public key string",
-    )?;
-    PublicKey::from_base64(&public_str).or_throw(&mut cx, "cannot base64 decode the public key")?
-  };
+  let public_key = utils::public_key_arg(
+    cx,
+    0,
+    "expected `public` argument to be the keys object or the public key string",
+    "cannot base64 decode the public key",
+  )?;
 
   let hmac_key = {
-    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(&mut cx) {
-      let authkey = cx.argument::<JsValue>(1).and_then(|v| {
-        if let Some(buf) = v.try_downcast::<JsBuffer>() {
-          let bytes = cx.borrow(&buf, |data| data.as_slice::<u8>());
-          AuthKey::from_slice(bytes).or_throw(&mut cx, "hmac_key buffer must be 32 bytes")
-        } else if let Some(s) = v.try_downcast::<JsString>() {
-          AuthKey::from_base64(&s.value())
-            .or_throw(&mut cx, "expected 2nd argument to be a base64 string")
-        } else {
-          cx.throw_error("expected 2nd argument to be a Buffer for the hmac_key")
-        }
-      })?;
-      Some(authkey)
+    if argc == 3 && cx.argument::<JsValue>(1)?.is_truthy(cx) {
+      Some(hmac_key_arg(cx, 1, "2nd")?)
     } else {
       None
     }
   };
 
-  let verify_obj = {
-    let (index, ord) = if argc == 2 { (1, "2nd") } else { (2, "3rd") };
-    let v = cx.argument::<JsValue>(index)?;
-    let obj = if v.is_a::<JsObject>() {
-      Ok(v.downcast::<JsObject>().unwrap())
-    } else {
-      cx.throw_error(format!(
-        "expected {} arg to be object, was a {}",
-        ord,
-        type_name(&v)
-      ))
-    }?;
-    utils::clone_js_obj(&mut cx, obj)?
-  };
+  let verify_obj = obj_arg(cx, argc)?;
 
   let signature = {
-    let mut sig = verify_obj
-      .get(&mut cx, "signature")
-      .or_else(|_| cx.throw_error("obj.signature field is missing from obj"))?
-      .downcast::<JsString>()
-      .or_throw(&mut cx)
-      .or_else(|_| cx.throw_error("obj.signature field is corrupted or not a string"))?
+    let sig_index = if argc == 2 { 1 } else { 2 };
+    let sig: Handle<JsValue> = verify_obj.get(cx, "signature")?;
+    let sig = sig
+      .try_downcast::<JsString>()
+      .or_throw_code(
+        cx,
+        CodedError::new(
+          errors::BAD_SIGNATURE,
+          "obj.signature field is corrupted or not a string",
+        )
+        .arg(sig_index),
+      )?
       .value();
-    match sig.rfind(".sig.ed25519") {
-      None => return cx.throw_error("Invalid signature string, is missing dot suffix"),
-      Some(dot_index) => sig.truncate(dot_index)
-    };
-    Signature::from_base64(&sig).or_throw(&mut cx, "unable to decode signature base64 string")?
+    signature_from_str(cx, sig, sig_index)?
   };
 
-  let msg = {
-    let undef = cx.undefined();
-    verify_obj
-      .set(&mut cx, "signature", undef) // `delete` keyword in JS would be better
-      .or_else(|_| cx.throw_error("failed to remove the `signature` field from the object"))?;
-
-    let args = ArrayVec::from([
-      verify_obj.upcast(),
-      cx.null().upcast(),
-      cx.number(2).upcast(),
-    ]);
-    utils::json_stringify(&mut cx, args)?.value().into_bytes()
-  };
+  let msg = legacy_json::stringify_unsigned(cx, verify_obj)?.into_bytes();
 
-  let passed = match hmac_key {
-    None => public_key.verify(&signature, msg.as_slice()),
-    Some(hmac_key) => {
-      let tag = hmac_key.authenticate(msg.as_slice());
-      public_key.verify(&signature, &tag.0)
-    }
-  };
+  Ok(Verifying {
+    public_key,
+    signature,
+    hmac_key,
+    msg,
+  })
+}
+
+// verify: (keys: obj | string, hmac_key?: string, o: obj) => boolean
+pub fn neon_verify_obj(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let argc = cx.len();
+  let verifying = verify_obj_args(&mut cx, argc, "verifyObj")?;
+  Ok(cx.boolean(verifying.verify()))
+}
 
-  Ok(cx.boolean(passed))
+// verifyObjAsync: (keys: obj | string, hmac_key?: string, o: obj, cb: (err, boolean) => void) => void
+pub fn neon_verify_obj_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "verifyObjAsync")?;
+  let argc = cx.len() - 1;
+  let verifying = verify_obj_args(&mut cx, argc, "verifyObjAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || verifying.verify(),
+    |cx, _, passed| Ok(cx.boolean(passed).upcast()),
+  );
+  Ok(cx.undefined())
 }
@@ -1,71 +1,95 @@
-use super::utils::{self, get_string_or_field, ContextExt, OptionExt};
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::pool;
+use super::utils::{self, get_string_or_field, ContextExt};
 use arrayvec::ArrayVec;
 use neon::prelude::*;
 use ssb_crypto::ephemeral::sk_to_curve;
 use ssb_crypto::{Keypair, PublicKey};
+use zeroize::Zeroizing;
 
-pub fn neon_box(mut cx: FunctionContext) -> JsResult<JsString> {
+fn box_args(cx: &mut FunctionContext) -> NeonResult<(Zeroizing<Vec<u8>>, Vec<PublicKey>)> {
   let arg1 = cx.argument::<JsValue>(0)?;
-  let msg = utils::json_stringify(&mut cx, ArrayVec::from([arg1]))?
-    .value()
-    .into_bytes();
-
-  let recps: Vec<PublicKey> = cx
+  let msg = Zeroizing::new(
+    utils::json_stringify(cx, ArrayVec::from([arg1]))?
+      .value()
+      .into_bytes(),
+  );
+
+  let mut recps: Vec<PublicKey> = vec![];
+  for recp in cx
     .arg_as::<JsArray>(1, "expected 2nd argument to be an array of recipients")?
-    .to_vec(&mut cx)?
-    .iter()
-    .flat_map(|recp| {
-      let public_str = get_string_or_field(&mut cx, *recp, "public").or_throw(
-        &mut cx,
+    .to_vec(cx)?
+  {
+    let public_str = get_string_or_field(cx, recp, "public").or_throw_code(
+      cx,
+      CodedError::type_error(
+        errors::INVALID_ARG_TYPE,
         "each recipient must be a keys object or public key string",
-      )?;
-      PublicKey::from_base64(&public_str).or_throw(&mut cx, "cannot base64 decode the public key")
-    })
-    .collect();
+      )
+      .arg(1),
+    )?;
+    let public = PublicKey::from_base64(&public_str).or_throw_code(
+      cx,
+      CodedError::new(
+        errors::BAD_PUBLIC_KEY,
+        "cannot base64 decode the public key",
+      )
+      .arg(1),
+    )?;
+    recps.push(public);
+  }
+  Ok((msg, recps))
+}
 
-  let multiboxed = private_box::encrypt(msg.as_slice(), recps.as_slice());
+fn multibox(msg: &[u8], recps: &[PublicKey]) -> String {
+  let multiboxed = private_box::encrypt(msg, recps);
   let mut out = base64::encode_config(multiboxed.as_slice(), base64::STANDARD);
   out.push_str(".box");
+  out
+}
 
-  Ok(cx.string(out))
+pub fn neon_box(mut cx: FunctionContext) -> JsResult<JsString> {
+  let (msg, recps) = box_args(&mut cx)?;
+  Ok(cx.string(multibox(&msg, &recps)))
 }
 
-pub fn neon_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
-  let cyphertext = {
-    let ctxt_str = cx
-      .arg_as::<JsString>(0, "expected 1st argument to be the cyphertext as a string")?
-      .value();
-    base64::decode_config(ctxt_str.trim_end_matches(".box"), base64::STANDARD)
-  };
-  if cyphertext.is_err() {
-    return Ok(cx.undefined().upcast());
-  }
-  let cyphertext = cyphertext.unwrap();
+// boxAsync: (msg: any, recps: Array<obj | string>, cb: (err, string) => void) => void
+pub fn neon_box_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "boxAsync")?;
+  let (msg, recps) = box_args(&mut cx)?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || multibox(&msg, &recps),
+    |cx, _, boxed| Ok(cx.string(boxed).upcast()),
+  );
+  Ok(cx.undefined())
+}
 
-  let private_key = {
-    let v = cx.argument(1)?;
-    let private_str = get_string_or_field(&mut cx, v, "private").or_throw(
-      &mut cx,
-      "expected 2nd argument to be the keys object or the private key string",
-    )?;
-    Keypair::from_base64(&private_str).or_throw(
-      &mut cx,
-      "cannot base64 decode the private key given to `signObj`",
-    )?
-  };
+// The cyphertext given to the unbox functions, or None when it isn't base64,
+// which makes them return undefined
+fn cyphertext_arg(cx: &mut FunctionContext) -> NeonResult<Option<Vec<u8>>> {
+  let ctxt_str = cx
+    .arg_as::<JsString>(0, "expected 1st argument to be the cyphertext as a string")?
+    .value();
+  Ok(base64::decode_config(ctxt_str.trim_end_matches(".box"), base64::STANDARD).ok())
+}
 
-  let msg = private_box::decrypt(cyphertext.as_slice(), &private_key).ok_or(0);
-  if msg.is_err() {
+// The JSON value in a decrypted message, or undefined
+fn parse_msg<'a>(cx: &mut impl Context<'a>, msg: Option<&[u8]>) -> JsResult<'a, JsValue> {
+  if msg.is_none() {
     return Ok(cx.undefined().upcast());
   }
   let msg = msg.unwrap();
-  let msg_str = String::from_utf8(msg);
+  let msg_str = std::str::from_utf8(msg);
   if msg_str.is_err() {
     return Ok(cx.undefined().upcast());
   }
   let msg_str = cx.string(msg_str.unwrap());
 
-  let out = utils::json_parse(&mut cx, msg_str);
+  let out = utils::json_parse(cx, msg_str);
   if out.is_err() {
     return Ok(cx.undefined().upcast());
   }
@@ -74,94 +98,147 @@ pub fn neon_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
   Ok(out.upcast())
 }
 
-pub fn neon_unbox_key(mut cx: FunctionContext) -> JsResult<JsValue> {
-  let cyphertext = {
-    let ctxt_str = cx
-      .arg_as::<JsString>(0, "expected 1st argument to be the cyphertext as a string")?
-      .value();
-    base64::decode_config(ctxt_str.trim_end_matches(".box"), base64::STANDARD)
-  };
-  if cyphertext.is_err() {
-    return Ok(cx.undefined().upcast());
+// The decoded cyphertext, with the keys or the message key to open it
+type Unboxing = (Vec<u8>, Keypair);
+type BodyUnboxing = (Vec<u8>, Zeroizing<Vec<u8>>);
+
+// None if the cyphertext can't be decoded, in which case the keys aren't read
+fn unbox_args(cx: &mut FunctionContext, name: &str) -> NeonResult<Option<Unboxing>> {
+  let cyphertext = cyphertext_arg(cx)?;
+  if cyphertext.is_none() {
+    return Ok(None);
   }
   let cyphertext = cyphertext.unwrap();
 
-  let keypair = {
-    let v = cx.argument(1)?;
-    let private_str = get_string_or_field(&mut cx, v, "private").or_throw(
-      &mut cx,
-      "expected 2nd argument to be the keys object or the private key string",
-    )?;
-    Keypair::from_base64(&private_str).or_throw(
-      &mut cx,
-      "cannot base64 decode the private key given to `signObj`",
-    )?
-  };
+  let keypair = utils::keypair_arg(
+    cx,
+    1,
+    "expected 2nd argument to be the keys object or the private key string",
+    &format!("cannot base64 decode the private key given to `{}`", name),
+  )?;
+  Ok(Some((cyphertext, keypair)))
+}
 
-  let opened_key = private_box::decrypt_key(&cyphertext, &keypair);
-  if opened_key.is_none() {
-    return Ok(cx.undefined().upcast());
+fn open(args: Option<Unboxing>) -> Option<Zeroizing<Vec<u8>>> {
+  let (cyphertext, keypair) = args?;
+  private_box::decrypt(cyphertext.as_slice(), &keypair).map(Zeroizing::new)
+}
+
+pub fn neon_unbox(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let args = unbox_args(&mut cx, "unbox")?;
+  let msg = open(args);
+  parse_msg(&mut cx, msg.as_deref().map(Vec::as_slice))
+}
+
+// unboxAsync: (boxed: string, keys: obj | string, cb: (err, any) => void) => void
+pub fn neon_unbox_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "unboxAsync")?;
+  let args = unbox_args(&mut cx, "unboxAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || open(args),
+    |cx, _, msg| parse_msg(cx, msg.as_deref().map(Vec::as_slice)),
+  );
+  Ok(cx.undefined())
+}
+
+fn open_key(args: Option<Unboxing>) -> Option<Zeroizing<[u8; 33]>> {
+  let (cyphertext, keypair) = args?;
+  let opened_key = private_box::decrypt_key(&cyphertext, &keypair)?;
+  Some(Zeroizing::new(opened_key.as_array()))
+}
+
+fn key_to_buffer<'a>(
+  cx: &mut impl Context<'a>,
+  opened_key: Option<Zeroizing<[u8; 33]>>,
+) -> JsResult<'a, JsValue> {
+  match opened_key {
+    None => Ok(cx.undefined().upcast()),
+    Some(opened_key) => Ok(utils::bytes_to_buffer(cx, &opened_key[..])?.upcast()),
   }
+}
 
-  let buffer = utils::bytes_to_buffer(&mut cx, &opened_key.unwrap().as_array())?;
-  Ok(buffer.upcast())
+pub fn neon_unbox_key(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let args = unbox_args(&mut cx, "unboxKey")?;
+  let opened_key = open_key(args);
+  key_to_buffer(&mut cx, opened_key)
+}
+
+// unboxKeyAsync: (boxed: string, keys: obj | string, cb: (err, Buffer) => void) => void
+pub fn neon_unbox_key_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "unboxKeyAsync")?;
+  let args = unbox_args(&mut cx, "unboxKeyAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || open_key(args),
+    |cx, _, opened_key| key_to_buffer(cx, opened_key),
+  );
+  Ok(cx.undefined())
 }
 
 // TODO should also allow JsBuffer ciphertext
-pub fn neon_unbox_body(mut cx: FunctionContext) -> JsResult<JsValue> {
-  let cyphertext = {
-    let ctxt_str = cx
-      .arg_as::<JsString>(0, "expected 1st argument to be the cyphertext as a string")?
-      .value();
-    base64::decode_config(ctxt_str.trim_end_matches(".box"), base64::STANDARD)
-  };
-  if cyphertext.is_err() {
-    return Ok(cx.undefined().upcast());
+fn unbox_body_args(cx: &mut FunctionContext) -> NeonResult<Option<BodyUnboxing>> {
+  let cyphertext = cyphertext_arg(cx)?;
+  if cyphertext.is_none() {
+    return Ok(None);
   }
   let cyphertext = cyphertext.unwrap();
 
   let opened_key_buf =
     cx.arg_as::<JsBuffer>(1, "expected 2nd argument to be a buffer for the opened key")?;
-  let opened_key = cx.borrow(&opened_key_buf, |data| data.as_slice::<u8>());
-
-  let msg = private_box::decrypt_body_with_key_bytes(&cyphertext, &opened_key);
+  let opened_key =
+    Zeroizing::new(cx.borrow(&opened_key_buf, |data| data.as_slice::<u8>().to_vec()));
+  Ok(Some((cyphertext, opened_key)))
+}
 
-  if msg.is_none() {
-    return Ok(cx.undefined().upcast());
-  }
-  let msg_str = String::from_utf8(msg.unwrap());
-  if msg_str.is_err() {
-    return Ok(cx.undefined().upcast());
-  }
-  let msg_str = cx.string(msg_str.unwrap());
+fn open_body(args: Option<BodyUnboxing>) -> Option<Zeroizing<Vec<u8>>> {
+  let (cyphertext, opened_key) = args?;
+  private_box::decrypt_body_with_key_bytes(&cyphertext, &opened_key).map(Zeroizing::new)
+}
 
-  let out = utils::json_parse(&mut cx, msg_str);
-  if out.is_err() {
-    return Ok(cx.undefined().upcast());
-  }
-  let out = out.unwrap();
+pub fn neon_unbox_body(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let args = unbox_body_args(&mut cx)?;
+  let msg = open_body(args);
+  parse_msg(&mut cx, msg.as_deref().map(Vec::as_slice))
+}
 
-  Ok(out.upcast())
+// unboxBodyAsync: (boxed: string, msgKey: Buffer, cb: (err, any) => void) => void
+pub fn neon_unbox_body_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "unboxBodyAsync")?;
+  let args = unbox_body_args(&mut cx)?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || open_body(args),
+    |cx, _, msg| parse_msg(cx, msg.as_deref().map(Vec::as_slice)),
+  );
+  Ok(cx.undefined())
 }
 
 // ssbSecretKeyToPrivateBoxSecret
 pub fn neon_sk_to_curve(mut cx: FunctionContext) -> JsResult<JsValue> {
-  let keypair = {
-    let v = cx.argument(0)?;
-    let private_str = get_string_or_field(&mut cx, v, "private").or_throw(
-      &mut cx,
-      "expected 1st argument to be the keys object or the private key string",
-    )?;
-
-    Keypair::from_base64(&private_str).or_throw(
-      &mut cx,
-      "cannot base64 decode the private key given to `signObj`",
-    )?
-  };
+  let keypair = utils::keypair_arg(
+    &mut cx,
+    0,
+    "expected 1st argument to be the keys object or the private key string",
+    "cannot base64 decode the private key given to `ssbSecretKeyToPrivateBoxSecret`",
+  )?;
 
   let curve = sk_to_curve(&keypair.secret);
   if curve.is_none() {
-    return cx.throw_error("failed to run ssbSecretKeyToPrivateBoxSecret");
+    return throw_code(
+      &mut cx,
+      errors::INTERNAL,
+      "failed to run ssbSecretKeyToPrivateBoxSecret",
+    );
   }
 
   let buffer = utils::bytes_to_buffer(&mut cx, &curve.unwrap().0)?;
@@ -1,14 +1,18 @@
+use super::errors::{self, CodedError, OptionCodeExt};
+use super::identity::JsIdentity;
+use super::wipe;
 use arrayvec::ArrayVec;
 use neon::handle::Managed;
 use neon::object::This;
 use neon::prelude::*;
-use ssb_crypto::Keypair;
+use ssb_crypto::{Keypair, PublicKey};
+use zeroize::Zeroizing;
 
 pub fn make_keys_obj<'a>(cx: &mut impl Context<'a>, kp: &Keypair) -> JsResult<'a, JsObject> {
   let keys_obj = JsObject::new(cx);
   let curve_val = cx.string("ed25519");
   let id_val = cx.string(kp.public.as_base64().wrap('@', ".ed25519"));
-  let private_val = cx.string(kp.as_base64().with_suffix(".ed25519"));
+  let private_val = cx.string(&*wipe::keypair_to_base64(kp, ".ed25519"));
   let public_val = cx.string(kp.public.as_base64().with_suffix(".ed25519"));
   keys_obj.set(cx, "curve", curve_val)?;
   keys_obj.set(cx, "id", id_val)?;
@@ -17,6 +21,21 @@ pub fn make_keys_obj<'a>(cx: &mut impl Context<'a>, kp: &Keypair) -> JsResult<'a
   Ok(keys_obj)
 }
 
+// Like `make_keys_obj` but without `private`, for when only the public key is known
+pub fn make_public_keys_obj<'a>(
+  cx: &mut impl Context<'a>,
+  public: &PublicKey,
+) -> JsResult<'a, JsObject> {
+  let keys_obj = JsObject::new(cx);
+  let curve_val = cx.string("ed25519");
+  let id_val = cx.string(public.as_base64().wrap('@', ".ed25519"));
+  let public_val = cx.string(public.as_base64().with_suffix(".ed25519"));
+  keys_obj.set(cx, "curve", curve_val)?;
+  keys_obj.set(cx, "id", id_val)?;
+  keys_obj.set(cx, "public", public_val)?;
+  Ok(keys_obj)
+}
+
 pub fn call_builtin<'a, T>(
   cx: &mut impl Context<'a>,
   module: &str,
@@ -61,25 +80,6 @@ pub fn buffer_from<'a>(
   call_builtin(cx, "Buffer", "from", args)
 }
 
-// TODO publish to some neon-helpers library?
-pub fn clone_js_obj<'a>(
-  cx: &mut impl Context<'a>,
-  obj: Handle<JsObject>,
-) -> JsResult<'a, JsObject> {
-  let new_obj = cx.empty_object();
-  let keys = obj.get_own_property_names(cx)?;
-  for i in 0..keys.len() {
-    let key = keys
-      .get(cx, i)?
-      .downcast::<JsString>()
-      .or_throw(cx)?
-      .value();
-    let val = obj.get(cx, key.as_str())?;
-    new_obj.set(cx, key.as_str(), val)?;
-  }
-  Ok(new_obj)
-}
-
 pub fn bytes_to_buffer<'a>(cx: &mut impl Context<'a>, bytes: &[u8]) -> JsResult<'a, JsBuffer> {
   let mut buffer = cx.buffer(bytes.len() as u32)?;
   cx.borrow_mut(&mut buffer, |data| {
@@ -104,6 +104,28 @@ pub fn get_string_or_field<'a, T: This>(
   }
 }
 
+// Reads `field` of the options object given as the argument at `index`,
+// treating `undefined` and `null` as absent
+pub fn get_field_opt<'a, V: Value>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<JsObject>,
+  index: i32,
+  field: &str,
+  msg: &str,
+) -> NeonResult<Option<Handle<'a, V>>> {
+  let v: Handle<JsValue> = obj.get(cx, field)?;
+  if v.is_a::<JsUndefined>() || v.is_a::<JsNull>() {
+    Ok(None)
+  } else {
+    v.try_downcast::<V>()
+      .or_throw_code(
+        cx,
+        CodedError::type_error(errors::INVALID_ARG_TYPE, msg).arg(index),
+      )
+      .map(Some)
+  }
+}
+
 pub fn type_name(v: &Handle<JsValue>) -> &'static str {
   if v.is_a::<JsArray>() {
     "array"
@@ -147,25 +169,6 @@ impl StringExt for String {
   }
 }
 
-pub trait OptionExt<T> {
-  fn or_throw<'a, S: AsRef<str>>(
-    self,
-    cx: &mut impl Context<'a>,
-    msg: S,
-  ) -> Result<T, neon::result::Throw>;
-}
-
-impl<T> OptionExt<T> for Option<T> {
-  fn or_throw<'a, S: AsRef<str>>(
-    self,
-    cx: &mut impl Context<'a>,
-    msg: S,
-  ) -> Result<T, neon::result::Throw> {
-    // Result<T, _>::unwrap_err and expect_err require T: Debug, which JsArray doesn't impl
-    self.ok_or_else(|| cx.throw_error::<_, T>(msg).err().unwrap())
-  }
-}
-
 // `if let Ok(s) = v.downcast::<JsString>() { ... }`
 // can be used with zero cost (aside from the type tag check)
 // when this PR is merged: https://github.com/neon-bindings/neon/pull/606
@@ -206,6 +209,65 @@ impl<T: Value + Managed> ValueExt for T {
   }
 }
 
+// Reads the identity handle, keys object or private key string at `index`
+pub fn keypair_arg(
+  cx: &mut FunctionContext,
+  index: i32,
+  expected_msg: &str,
+  decode_msg: &str,
+) -> NeonResult<Keypair> {
+  let arg = cx
+    .argument_opt(index)
+    .unwrap_or_else(|| cx.undefined().upcast());
+  if let Some(identity) = arg.try_downcast::<JsIdentity>() {
+    return Ok(cx.borrow(&identity, |identity| (*identity.keypair).clone()));
+  }
+  let private_str = get_string_or_field(cx, arg, "private")
+    .map(Zeroizing::new)
+    .or_throw_code(
+      cx,
+      CodedError::type_error(errors::INVALID_ARG_TYPE, expected_msg).arg(index),
+    )?;
+  wipe::keypair_from_base64(&private_str).or_throw_code(
+    cx,
+    CodedError::new(errors::BAD_PRIVATE_KEY, decode_msg).arg(index),
+  )
+}
+
+// Reads the identity handle, keys object or public key string at `index`
+pub fn public_key_arg(
+  cx: &mut FunctionContext,
+  index: i32,
+  expected_msg: &str,
+  decode_msg: &str,
+) -> NeonResult<PublicKey> {
+  let arg = cx
+    .argument_opt(index)
+    .unwrap_or_else(|| cx.undefined().upcast());
+  public_key_value(cx, arg, index, expected_msg, decode_msg)
+}
+
+// Like `public_key_arg`, for a value found inside the argument at `index`
+pub fn public_key_value(
+  cx: &mut FunctionContext,
+  arg: Handle<JsValue>,
+  index: i32,
+  expected_msg: &str,
+  decode_msg: &str,
+) -> NeonResult<PublicKey> {
+  if let Some(identity) = arg.try_downcast::<JsIdentity>() {
+    return Ok(cx.borrow(&identity, |identity| identity.keypair.public));
+  }
+  let public_str = get_string_or_field(cx, arg, "public").or_throw_code(
+    cx,
+    CodedError::type_error(errors::INVALID_ARG_TYPE, expected_msg).arg(index),
+  )?;
+  PublicKey::from_base64(&public_str).or_throw_code(
+    cx,
+    CodedError::new(errors::BAD_PUBLIC_KEY, decode_msg).arg(index),
+  )
+}
+
 // Like `cx.argument::<T>(index)?` but with a custom error msg
 pub trait ContextExt<'a> {
   fn arg_as<T: Value>(
@@ -221,7 +283,12 @@ impl<'a> ContextExt<'a> for FunctionContext<'a> {
     index: i32,
     msg: &str,
   ) -> Result<Handle<'a, T>, neon::result::Throw> {
-    let v = self.argument::<JsValue>(index)?;
-    v.try_downcast::<T>().or_throw(self, msg)
+    let v = self
+      .argument_opt(index)
+      .unwrap_or_else(|| self.undefined().upcast());
+    v.try_downcast::<T>().or_throw_code(
+      self,
+      CodedError::type_error(errors::INVALID_ARG_TYPE, msg).arg(index),
+    )
   }
 }
@@ -0,0 +1,534 @@
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::hash;
+use super::legacy_json;
+use super::sig::{self, hmac_key_arg};
+use super::utils::{ContextExt, HandleExt, ValueExt};
+use neon::prelude::*;
+use ssb_crypto::{Hash, NetworkKey as AuthKey, PublicKey, Signature};
+
+// Validation of legacy SSB messages, with the checks that ssb-validate does:
+// the shape of a message on its own, its signature by its author, and that
+// it follows the latest message of its feed
+
+// Counted in UTF-16 code units of JSON.stringify(msg, null, 2), like JS does,
+// so it is 8KB for latin1 text but can be more for other text
+const MAX_MESSAGE_LENGTH: usize = 8192;
+
+// The latest valid message of a feed, which the next one has to follow
+#[derive(Clone)]
+pub struct FeedState {
+  pub author: String,
+  pub id: String,
+  pub sequence: f64,
+  pub timestamp: f64,
+}
+
+enum Previous {
+  Null,
+  Id(String),
+  Other,
+}
+
+pub enum Content {
+  // With its `type`, if that is a string
+  Object(Option<String>),
+  Text(String),
+  Other,
+}
+
+// The fields of a message and the JSON that was signed, read on the main
+// thread so that it can be validated on any thread
+pub struct Message {
+  author: Option<String>,
+  sequence: Option<f64>,
+  previous: Previous,
+  timestamp: Option<f64>,
+  hash: Option<String>,
+  content: Content,
+  signature: Option<String>,
+  // Own property names, in order
+  keys: Vec<String>,
+  unsigned_json: String,
+}
+
+fn string_field<'a>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<'a, JsObject>,
+  name: &str,
+) -> NeonResult<Option<String>> {
+  let v: Handle<JsValue> = obj.get(cx, name)?;
+  Ok(v.try_downcast::<JsString>().map(|s| s.value()))
+}
+
+fn number_field<'a>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<'a, JsObject>,
+  name: &str,
+) -> NeonResult<Option<f64>> {
+  let v: Handle<JsValue> = obj.get(cx, name)?;
+  Ok(
+    v.try_downcast::<JsNumber>()
+      .map(|n| n.value())
+      .filter(|n| !n.is_nan()),
+  )
+}
+
+pub fn read_content<'a>(
+  cx: &mut impl Context<'a>,
+  content: Handle<'a, JsValue>,
+) -> NeonResult<Content> {
+  Ok(if let Some(text) = content.try_downcast::<JsString>() {
+    Content::Text(text.value())
+  } else if let Some(obj) = content
+    .try_downcast::<JsObject>()
+    .filter(|_| !content.is_a::<JsFunction>())
+  {
+    Content::Object(string_field(cx, obj, "type")?)
+  } else {
+    Content::Other
+  })
+}
+
+pub fn read_message<'a>(
+  cx: &mut impl Context<'a>,
+  obj: Handle<'a, JsObject>,
+) -> NeonResult<Message> {
+  let previous: Handle<JsValue> = obj.get(cx, "previous")?;
+  let previous = if previous.is_a::<JsNull>() {
+    Previous::Null
+  } else if let Some(id) = previous.try_downcast::<JsString>() {
+    Previous::Id(id.value())
+  } else {
+    Previous::Other
+  };
+
+  let content: Handle<JsValue> = obj.get(cx, "content")?;
+  let content = read_content(cx, content)?;
+
+  // Integer keys may come as numbers, and are in no valid message anyway
+  let keys = obj
+    .get_own_property_names(cx)?
+    .to_vec(cx)?
+    .into_iter()
+    .map(|key| {
+      key
+        .try_downcast::<JsString>()
+        .map(|key| key.value())
+        .unwrap_or_default()
+    })
+    .collect();
+
+  Ok(Message {
+    author: string_field(cx, obj, "author")?,
+    sequence: number_field(cx, obj, "sequence")?,
+    previous,
+    timestamp: number_field(cx, obj, "timestamp")?,
+    hash: string_field(cx, obj, "hash")?,
+    content,
+    signature: string_field(cx, obj, "signature")?,
+    keys,
+    unsigned_json: legacy_json::stringify_unsigned(cx, obj)?,
+  })
+}
+
+fn invalid<S: Into<String>>(field: &'static str, message: S) -> CodedError {
+  CodedError::new(errors::INVALID_MESSAGE, message).field("field", field)
+}
+
+// `@<base64>.ed25519`
+pub fn feed_key(id: &str) -> Option<PublicKey> {
+  let key = id.strip_prefix('@')?.strip_suffix(".ed25519")?;
+  PublicKey::from_base64(key)
+}
+
+// `%<base64>.sha256`
+fn is_msg_id(id: &str) -> bool {
+  id.strip_prefix('%')
+    .and_then(|id| id.strip_suffix(".sha256"))
+    .and_then(Hash::from_base64)
+    .is_some()
+}
+
+// Like ssb-validate's `isEncrypted`: base64 followed by `.box`, which also
+// lets `.box2` through
+fn is_boxed(content: &str) -> bool {
+  let rest =
+    content.trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/');
+  let unpadded = rest.trim_start_matches('=');
+  rest.len() < content.len() && rest.len() - unpadded.len() <= 2 && unpadded.starts_with(".box")
+}
+
+// Like ssb-validate's `isValidOrder` for a signed message, which lets only
+// author and sequence swap places
+fn is_valid_order(keys: &[String]) -> bool {
+  matches!(
+    keys
+      .iter()
+      .map(String::as_str)
+      .collect::<Vec<_>>()
+      .as_slice(),
+    [
+      "previous",
+      "author",
+      "sequence",
+      "timestamp",
+      "hash",
+      "content",
+      "signature"
+    ] | [
+      "previous",
+      "sequence",
+      "author",
+      "timestamp",
+      "hash",
+      "content",
+      "signature"
+    ]
+  )
+}
+
+fn is_sequence(n: f64) -> bool {
+  n >= 1.0 && n.fract() == 0.0
+}
+
+pub fn check_content(content: &Content) -> Result<(), CodedError> {
+  match content {
+    Content::Object(Some(t)) if (3..=52).contains(&t.encode_utf16().count()) => Ok(()),
+    Content::Object(_) => Err(invalid(
+      "content",
+      "content type must be a string of 3 to 52 characters",
+    )),
+    Content::Text(text) if is_boxed(text) => Ok(()),
+    Content::Text(_) => Err(invalid("content", "content string must end in .box")),
+    Content::Other => Err(invalid(
+      "content",
+      "message content must be an object or a boxed string",
+    )),
+  }
+}
+
+// Of the JSON of the whole message, with its signature
+pub fn check_length(json: &str) -> Result<(), CodedError> {
+  if json.encode_utf16().count() > MAX_MESSAGE_LENGTH {
+    return Err(CodedError::new(
+      errors::INVALID_MESSAGE,
+      format!(
+        "encoded message must not be longer than {} characters",
+        MAX_MESSAGE_LENGTH
+      ),
+    ));
+  }
+  Ok(())
+}
+
+// The state of a feed after `msg`, which has to follow `state`, or None for
+// the first message of a feed
+pub fn validate(
+  state: Option<&FeedState>,
+  msg: &Message,
+  hmac_key: Option<&AuthKey>,
+) -> Result<FeedState, CodedError> {
+  let author = msg.author.as_deref().unwrap_or_default();
+  let public_key = feed_key(author)
+    .ok_or_else(|| invalid("author", "message author must be an ed25519 feed id"))?;
+  if let Some(state) = state {
+    if state.author != author {
+      return Err(invalid(
+        "author",
+        format!(
+          "expected a message by {} but found one by {}",
+          state.author, author
+        ),
+      ));
+    }
+  }
+
+  let sequence = msg
+    .sequence
+    .filter(|n| is_sequence(*n))
+    .ok_or_else(|| invalid("sequence", "message sequence must be a positive integer"))?;
+  match (&msg.previous, sequence == 1.0) {
+    (Previous::Null, true) => {}
+    (Previous::Id(id), false) if is_msg_id(id) => {}
+    (_, true) => {
+      return Err(invalid(
+        "previous",
+        "the first message of a feed must have previous: null",
+      ))
+    }
+    _ => return Err(invalid("previous", "message previous must be a message id")),
+  }
+  let timestamp = msg
+    .timestamp
+    .ok_or_else(|| invalid("timestamp", "message timestamp must be a number"))?;
+  if msg.hash.as_deref() != Some("sha256") {
+    return Err(invalid("hash", "message hash must be 'sha256'"));
+  }
+  if !is_valid_order(&msg.keys) {
+    return Err(CodedError::new(
+      errors::INVALID_MESSAGE,
+      "message must have keys in allowed order",
+    ));
+  }
+
+  check_content(&msg.content)?;
+
+  let not_signed = || {
+    CodedError::new(
+      errors::INVALID_SIGNATURE,
+      "message signature was not made by its author",
+    )
+    .field("field", "signature")
+  };
+  // The keys are in order, so the signature comes last in the JSON
+  let signature = msg.signature.as_deref().ok_or_else(not_signed)?;
+  let json = legacy_json::with_signature(&msg.unsigned_json, signature);
+  check_length(&json)?;
+
+  // Also checks that the signature is by the author's key, which is ed25519
+  let signed = signature
+    .strip_suffix(".sig.ed25519")
+    .and_then(Signature::from_base64)
+    .is_some_and(|sig| {
+      sig::verify_bytes(&public_key, &sig, hmac_key, msg.unsigned_json.as_bytes())
+    });
+  if !signed {
+    return Err(not_signed());
+  }
+
+  let id = hash::msg_id(&json);
+  match state {
+    Some(state) => follows(state, &id, sequence, &msg.previous)?,
+    None if sequence != 1.0 => {
+      return Err(CodedError::new(
+        errors::OUT_OF_ORDER,
+        format!("expected sequence 1 but found {}", sequence),
+      ))
+    }
+    None => {}
+  }
+
+  Ok(FeedState {
+    author: author.to_string(),
+    id,
+    sequence,
+    timestamp,
+  })
+}
+
+// Only called with a validly signed message, so another message at a known
+// sequence means the author forked their feed
+fn follows(
+  state: &FeedState,
+  id: &str,
+  sequence: f64,
+  previous: &Previous,
+) -> Result<(), CodedError> {
+  let fork = |at: f64| {
+    CodedError::new(
+      errors::FORK,
+      format!(
+        "fork detected: {} has two messages at sequence {}",
+        state.author, at
+      ),
+    )
+  };
+  if sequence == state.sequence + 1.0 {
+    match previous {
+      Previous::Id(previous) if previous == &state.id => Ok(()),
+      _ => Err(fork(state.sequence)),
+    }
+  } else if sequence == state.sequence && id != state.id {
+    Err(fork(sequence))
+  } else if sequence == state.sequence {
+    Err(CodedError::new(
+      errors::OUT_OF_ORDER,
+      format!("message {} was already validated", id),
+    ))
+  } else {
+    Err(CodedError::new(
+      errors::OUT_OF_ORDER,
+      format!(
+        "expected sequence {} but found {}",
+        state.sequence + 1.0,
+        sequence
+      ),
+    ))
+  }
+}
+
+// How far a run of messages of one feed got
+#[derive(Default)]
+pub struct FeedRun {
+  pub state: Option<FeedState>,
+  pub validated: usize,
+  pub error: Option<CodedError>,
+}
+
+// Validates the messages of `feed` in order, from `state`, stopping at the
+// first that fails
+pub fn validate_run(
+  feed: &str,
+  mut state: Option<FeedState>,
+  msgs: &[Message],
+  hmac_key: Option<&AuthKey>,
+) -> FeedRun {
+  for (i, msg) in msgs.iter().enumerate() {
+    let result = match &state {
+      None if msg.author.as_deref() != Some(feed) => {
+        Err(invalid("author", format!("expected a message by {}", feed)))
+      }
+      _ => validate(state.as_ref(), msg, hmac_key),
+    };
+    match result {
+      Ok(next) => state = Some(next),
+      Err(err) => {
+        return FeedRun {
+          state,
+          validated: i,
+          error: Some(err),
+        }
+      }
+    }
+  }
+  FeedRun {
+    state,
+    validated: msgs.len(),
+    error: None,
+  }
+}
+
+// A feed state object, or None for null and undefined
+pub fn state_value<'a>(
+  cx: &mut impl Context<'a>,
+  v: Handle<'a, JsValue>,
+  index: i32,
+) -> NeonResult<Option<FeedState>> {
+  if v.is_a::<JsNull>() || v.is_a::<JsUndefined>() {
+    return Ok(None);
+  }
+  let obj = v.try_downcast::<JsObject>().or_throw_code(
+    cx,
+    CodedError::type_error(
+      errors::INVALID_ARG_TYPE,
+      "expected the feed state to be an object or null",
+    )
+    .arg(index),
+  )?;
+  let author = string_field(cx, obj, "author")?;
+  let id = string_field(cx, obj, "id")?;
+  let sequence = number_field(cx, obj, "sequence")?;
+  let timestamp = number_field(cx, obj, "timestamp")?;
+  match (author, id, sequence, timestamp) {
+    (Some(author), Some(id), Some(sequence), Some(timestamp))
+      if feed_key(&author).is_some() && is_msg_id(&id) && is_sequence(sequence) =>
+    {
+      Ok(Some(FeedState {
+        author,
+        id,
+        sequence,
+        timestamp,
+      }))
+    }
+    _ => CodedError::new(
+      errors::INVALID_ARG_VALUE,
+      "expected the feed state to have an author, id, sequence and timestamp",
+    )
+    .arg(index)
+    .throw(cx),
+  }
+}
+
+pub fn state_to_js<'a>(cx: &mut impl Context<'a>, state: &FeedState) -> JsResult<'a, JsObject> {
+  let obj = cx.empty_object();
+  let author = cx.string(&state.author);
+  obj.set(cx, "author", author)?;
+  let id = cx.string(&state.id);
+  obj.set(cx, "id", id)?;
+  let sequence = cx.number(state.sequence);
+  obj.set(cx, "sequence", sequence)?;
+  let timestamp = cx.number(state.timestamp);
+  obj.set(cx, "timestamp", timestamp)?;
+  Ok(obj)
+}
+
+// The message in the argument at `index`
+pub fn message_value<'a>(
+  cx: &mut impl Context<'a>,
+  v: Handle<'a, JsValue>,
+  index: i32,
+  expected_msg: &str,
+) -> NeonResult<Message> {
+  let obj = v.try_downcast::<JsObject>().or_throw_code(
+    cx,
+    CodedError::type_error(errors::INVALID_ARG_TYPE, expected_msg).arg(index),
+  )?;
+  read_message(cx, obj)
+}
+
+// The state and hmac_key arguments, which come before the messages
+fn feed_args(
+  cx: &mut FunctionContext,
+  name: &str,
+) -> NeonResult<(Option<FeedState>, Option<AuthKey>)> {
+  if cx.len() < 3 {
+    return throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!("{} requires three arguments: (state, hmacKey, msg)", name),
+    );
+  }
+  let state = cx.argument::<JsValue>(0)?;
+  let state = state_value(cx, state, 0)?;
+  let hmac_key = if cx.argument::<JsValue>(1)?.is_truthy(cx) {
+    Some(hmac_key_arg(cx, 1, "2nd")?)
+  } else {
+    None
+  };
+  Ok((state, hmac_key))
+}
+
+// validate: (state: obj | null, hmac_key: Buffer | string | null, msg: obj) => obj
+pub fn neon_validate(mut cx: FunctionContext) -> JsResult<JsObject> {
+  let (state, hmac_key) = feed_args(&mut cx, "validate")?;
+  let msg = cx.argument::<JsValue>(2)?;
+  let msg = message_value(
+    &mut cx,
+    msg,
+    2,
+    "expected 3rd argument to be a message object",
+  )?;
+  match validate(state.as_ref(), &msg, hmac_key.as_ref()) {
+    Ok(state) => state_to_js(&mut cx, &state),
+    Err(err) => err.throw(&mut cx),
+  }
+}
+
+// validateBatch: (state: obj | null, hmac_key: Buffer | string | null, msgs: Array<obj>) => obj | null
+pub fn neon_validate_batch(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let (mut state, hmac_key) = feed_args(&mut cx, "validateBatch")?;
+  let expected_msg = "expected 3rd argument to be an array of message objects";
+  let msgs = cx.arg_as::<JsArray>(2, expected_msg)?.to_vec(&mut cx)?;
+  for (i, msg) in msgs.into_iter().enumerate() {
+    let msg = message_value(&mut cx, msg, 2, expected_msg)?;
+    match validate(state.as_ref(), &msg, hmac_key.as_ref()) {
+      Ok(next) => state = Some(next),
+      // With where the run stopped, and the state up to there
+      Err(err) => {
+        let err = err.into_js(&mut cx)?;
+        let index = cx.number(i as f64);
+        err.set(&mut cx, "index", index)?;
+        let state = match &state {
+          Some(state) => state_to_js(&mut cx, state)?.upcast(),
+          None => cx.null().upcast::<JsValue>(),
+        };
+        err.set(&mut cx, "state", state)?;
+        return cx.throw(err);
+      }
+    }
+  }
+  match state {
+    Some(state) => Ok(state_to_js(&mut cx, &state)?.upcast()),
+    None => Ok(cx.null().upcast()),
+  }
+}
@@ -0,0 +1,392 @@
+use super::errors::{self, CodedError, OptionCodeExt};
+use super::pool;
+use super::sig::hmac_key_arg;
+use super::utils::{get_field_opt, ContextExt, HandleExt, ValueExt};
+use super::validate::{self, FeedRun, Message};
+use neon::context::TaskContext;
+use neon::event::EventHandler;
+use neon::prelude::*;
+use ssb_crypto::NetworkKey as AuthKey;
+
+use std::collections::VecDeque;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+// Validation of many feeds at once. The messages are read on the main thread
+// in chunks of this many, taking turns between the feeds, so the event loop
+// isn't held up by the whole input, and the chunks of each feed are validated
+// in order on the thread pool
+const CHUNK_SIZE: usize = 1000;
+
+// So that reading doesn't get far ahead of the pool, it pauses once this many
+// messages have been read but not yet validated, until half of them are done
+const MAX_PENDING: usize = 64 * CHUNK_SIZE;
+
+const EXPECTED_FEEDS: &str = "expected 1st argument to be an object of message arrays by feed id";
+
+struct Feed {
+  id: String,
+  // Messages in its array, and how many of them were read
+  len: usize,
+  read: usize,
+  run: FeedRun,
+  chunks: VecDeque<Vec<Message>>,
+  // Whether a thread of the pool is working through `chunks`
+  running: bool,
+}
+
+struct Shared {
+  feeds: Vec<Feed>,
+  // Where to look for the next feed to read a chunk from
+  next: usize,
+  pending: usize,
+  paused: bool,
+  // Whether the results were taken for the callback
+  finished: bool,
+  // Whether the callback was called, with the results or an error
+  settled: bool,
+}
+
+impl Shared {
+  // The next feed with messages left to read, after the one read last
+  fn next_unread(&self) -> Option<usize> {
+    let n = self.feeds.len();
+    (0..n)
+      .map(|i| (self.next + i) % n)
+      .find(|&i| self.feeds[i].read < self.feeds[i].len)
+  }
+}
+
+struct Validation {
+  shared: Mutex<Shared>,
+  hmac_key: Option<AuthKey>,
+  // `this` is `{feeds, onProgress}`, with the message arrays in the order of
+  // `shared.feeds`
+  handler: EventHandler,
+  progress: bool,
+}
+
+impl Validation {
+  fn schedule_read(self: &Arc<Self>) {
+    let validation = self.clone();
+    self.handler.schedule_with(move |cx, this, callback| {
+      if let Err(err) = cx.try_catch(|cx| validation.read_chunk(cx, this)) {
+        validation.fail(cx, callback, err);
+      }
+    });
+  }
+
+  // Reads the next chunk, hands it to its feed and schedules the one after
+  fn read_chunk<'a>(
+    self: &Arc<Self>,
+    cx: &mut TaskContext<'a>,
+    this: Handle<JsValue>,
+  ) -> NeonResult<()> {
+    let (i, start, end) = {
+      let shared = self.shared.lock().unwrap();
+      match shared.next_unread() {
+        Some(i) if !shared.settled => {
+          let feed = &shared.feeds[i];
+          (i, feed.read, feed.len.min(feed.read + CHUNK_SIZE))
+        }
+        _ => return Ok(()),
+      }
+    };
+
+    let this: Handle<JsObject> = this.downcast_or_throw(cx)?;
+    let arrays: Handle<JsValue> = this.get(cx, "feeds")?;
+    let arrays: Handle<JsArray> = arrays.downcast_or_throw(cx)?;
+    let msgs: Handle<JsValue> = arrays.get(cx, i as u32)?;
+    let msgs: Handle<JsArray> = msgs.downcast_or_throw(cx)?;
+    let mut chunk = Vec::with_capacity(end - start);
+    for j in start..end {
+      let msg: Handle<JsValue> = msgs.get(cx, j as u32)?;
+      chunk.push(validate::message_value(cx, msg, 0, EXPECTED_FEEDS)?);
+    }
+
+    let mut shared = self.shared.lock().unwrap();
+    let feed = &mut shared.feeds[i];
+    feed.read = end;
+    feed.chunks.push_back(chunk);
+    let start_work = !feed.running;
+    feed.running = true;
+    shared.pending += end - start;
+    shared.next = i + 1;
+    let more = shared.next_unread().is_some();
+    shared.paused = more && shared.pending >= MAX_PENDING;
+    let read_more = more && !shared.paused;
+    drop(shared);
+
+    if start_work {
+      let validation = self.clone();
+      pool::submit(Box::new(move || validation.work(i)));
+    }
+    if read_more {
+      self.schedule_read();
+    }
+    Ok(())
+  }
+
+  // On the pool, validates the chunks of feed `i` until there are none left,
+  // skipping those after its first failure
+  fn work(self: &Arc<Self>, i: usize) {
+    loop {
+      let (chunk, id, run) = {
+        let mut shared = self.shared.lock().unwrap();
+        let settled = shared.settled;
+        let feed = &mut shared.feeds[i];
+        match feed.chunks.pop_front() {
+          Some(chunk) if !settled => {
+            let run = feed.run.error.is_none().then(|| feed.run.state.clone());
+            (chunk, feed.id.clone(), run)
+          }
+          _ => {
+            feed.running = false;
+            break;
+          }
+        }
+      };
+
+      let output = run.map(|state| {
+        let hmac_key = self.hmac_key.as_ref();
+        panic::catch_unwind(AssertUnwindSafe(|| {
+          validate::validate_run(&id, state, &chunk, hmac_key)
+        }))
+      });
+
+      let mut shared = self.shared.lock().unwrap();
+      shared.pending -= chunk.len();
+      let resume = shared.paused && shared.pending < MAX_PENDING / 2;
+      if resume {
+        shared.paused = false;
+      }
+      let run = &mut shared.feeds[i].run;
+      let validated = output.map(|output| {
+        match output {
+          Ok(output) => {
+            run.state = output.state;
+            run.validated += output.validated;
+            run.error = output.error;
+          }
+          Err(_) => run.error = Some(CodedError::new(errors::INTERNAL, "native task panicked")),
+        }
+        run.validated
+      });
+      drop(shared);
+
+      if resume {
+        self.schedule_read();
+      }
+      if let Some(validated) = validated.filter(|_| self.progress) {
+        self.report(id, validated);
+      }
+    }
+    self.finish_if_done();
+  }
+
+  // Calls `onProgress(feedId, validated)`, failing the whole validation if it
+  // throws
+  fn report(self: &Arc<Self>, feed: String, validated: usize) {
+    let validation = self.clone();
+    self.handler.schedule_with(move |cx, this, callback| {
+      if validation.shared.lock().unwrap().settled {
+        return;
+      }
+      let result = cx.try_catch(|cx| {
+        let this: Handle<JsObject> = this.downcast_or_throw(cx)?;
+        let on_progress: Handle<JsValue> = this.get(cx, "onProgress")?;
+        let on_progress: Handle<JsFunction> = on_progress.downcast_or_throw(cx)?;
+        let args: Vec<Handle<JsValue>> = vec![
+          cx.string(feed).upcast(),
+          cx.number(validated as f64).upcast(),
+        ];
+        let undefined = cx.undefined();
+        on_progress.call(cx, undefined, args)?;
+        Ok(())
+      });
+      if let Err(err) = result {
+        validation.fail(cx, callback, err);
+      }
+    });
+  }
+
+  // Once every message is read and validated, calls the callback with
+  // `{[feedId]: {state, validated, error}}`
+  fn finish_if_done(self: &Arc<Self>) {
+    let mut shared = self.shared.lock().unwrap();
+    let busy = shared
+      .feeds
+      .iter()
+      .any(|feed| feed.running || feed.read < feed.len);
+    if busy || shared.finished || shared.settled {
+      return;
+    }
+    shared.finished = true;
+    let runs: Vec<(String, FeedRun)> = shared
+      .feeds
+      .iter_mut()
+      .map(|feed| (feed.id.clone(), mem::take(&mut feed.run)))
+      .collect();
+    drop(shared);
+
+    let validation = self.clone();
+    self.handler.schedule_with(move |cx, _, callback| {
+      if !validation.settle() {
+        return;
+      }
+      let result = cx.try_catch(|cx| {
+        let results = cx.empty_object();
+        for (feed, run) in runs {
+          let run = run_to_js(cx, run)?;
+          results.set(cx, feed.as_str(), run)?;
+        }
+        Ok(results.upcast())
+      });
+      let args = match result {
+        Ok(results) => vec![cx.null().upcast(), results],
+        Err(err) => vec![err],
+      };
+      let null = cx.null();
+      let _ = callback.call(cx, null, args);
+    });
+  }
+
+  // Calls the callback with `err`, unless it was already called
+  fn fail<'a>(
+    &self,
+    cx: &mut TaskContext<'a>,
+    callback: Handle<JsFunction>,
+    err: Handle<'a, JsValue>,
+  ) {
+    if self.settle() {
+      let null = cx.null();
+      let _ = callback.call(cx, null, vec![err]);
+    }
+  }
+
+  // Whether the callback is still to be called, which it then is
+  fn settle(&self) -> bool {
+    let mut shared = self.shared.lock().unwrap();
+    !mem::replace(&mut shared.settled, true)
+  }
+}
+
+fn run_to_js<'a>(cx: &mut impl Context<'a>, run: FeedRun) -> JsResult<'a, JsObject> {
+  let obj = cx.empty_object();
+  let state = match &run.state {
+    Some(state) => validate::state_to_js(cx, state)?.upcast(),
+    None => cx.null().upcast::<JsValue>(),
+  };
+  obj.set(cx, "state", state)?;
+  let validated = cx.number(run.validated as f64);
+  obj.set(cx, "validated", validated)?;
+  let error = match run.error {
+    Some(err) => err.into_js(cx)?.upcast(),
+    None => cx.null().upcast::<JsValue>(),
+  };
+  obj.set(cx, "error", error)?;
+  Ok(obj)
+}
+
+// validateFeeds: (feeds: {[feedId]: Array<obj>}, hmac_key?: Buffer | string | null, opts?: {states?: {[feedId]: obj}, onProgress?: (feedId: string, validated: number) => void} | null, cb: (err, obj) => void) => void
+pub fn neon_validate_feeds(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "validateFeeds")?;
+  let argc = cx.len() - 1;
+  let feeds = cx.arg_as::<JsObject>(0, EXPECTED_FEEDS)?;
+  let hmac_key = if argc >= 2 && cx.argument::<JsValue>(1)?.is_truthy(&mut cx) {
+    Some(hmac_key_arg(&mut cx, 1, "2nd")?)
+  } else {
+    None
+  };
+  let (states, on_progress) = match cx.argument_opt(2) {
+    Some(opts) if argc >= 3 && !(opts.is_a::<JsNull>() || opts.is_a::<JsUndefined>()) => {
+      let opts = cx.arg_as::<JsObject>(2, "expected 3rd argument to be an options object")?;
+      let states = get_field_opt::<JsObject>(
+        &mut cx,
+        opts,
+        2,
+        "states",
+        "opts.states must be an object of feed states",
+      )?;
+      let on_progress = get_field_opt::<JsFunction>(
+        &mut cx,
+        opts,
+        2,
+        "onProgress",
+        "opts.onProgress must be a function",
+      )?;
+      (states, on_progress)
+    }
+    _ => (None, None),
+  };
+
+  let names = feeds.get_own_property_names(&mut cx)?.to_vec(&mut cx)?;
+  let arrays = JsArray::new(&mut cx, names.len() as u32);
+  let mut list = Vec::with_capacity(names.len());
+  for (i, name) in names.into_iter().enumerate() {
+    // Integer keys may come as numbers, and are no feed ids anyway
+    let feed = match name.try_downcast::<JsString>() {
+      Some(feed) => feed.value(),
+      None => String::new(),
+    };
+    if validate::feed_key(&feed).is_none() {
+      return CodedError::new(
+        errors::INVALID_ARG_VALUE,
+        format!(
+          "expected the keys of the 1st argument to be feed ids, found {}",
+          feed
+        ),
+      )
+      .arg(0)
+      .throw(&mut cx);
+    }
+    let msgs: Handle<JsValue> = feeds.get(&mut cx, name)?;
+    let msgs = msgs.try_downcast::<JsArray>().or_throw_code(
+      &mut cx,
+      CodedError::type_error(errors::INVALID_ARG_TYPE, EXPECTED_FEEDS).arg(0),
+    )?;
+    arrays.set(&mut cx, i as u32, msgs)?;
+    let state = match states {
+      Some(states) => {
+        let state = states.get(&mut cx, name)?;
+        validate::state_value(&mut cx, state, 2)?
+      }
+      None => None,
+    };
+    list.push(Feed {
+      id: feed,
+      len: msgs.len() as usize,
+      read: 0,
+      run: FeedRun {
+        state,
+        ..FeedRun::default()
+      },
+      chunks: VecDeque::new(),
+      running: false,
+    });
+  }
+
+  // The arrays are read later on, and kept alive until then with `this`
+  let this = cx.empty_object();
+  this.set(&mut cx, "feeds", arrays)?;
+  if let Some(on_progress) = on_progress {
+    this.set(&mut cx, "onProgress", on_progress)?;
+  }
+  let validation = Arc::new(Validation {
+    shared: Mutex::new(Shared {
+      feeds: list,
+      next: 0,
+      pending: 0,
+      paused: false,
+      finished: false,
+      settled: false,
+    }),
+    hmac_key,
+    handler: EventHandler::new(&cx, this, callback),
+    progress: on_progress.is_some(),
+  });
+  validation.schedule_read();
+  validation.finish_if_done();
+  Ok(cx.undefined())
+}
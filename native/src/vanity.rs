@@ -0,0 +1,288 @@
+use super::errors::{self, throw_code, CodedError, OptionCodeExt};
+use super::utils::{get_field_opt, make_keys_obj, ContextExt, HandleExt, StringExt};
+use neon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use ssb_crypto::Keypair;
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Searches that are still running, so that `cancelVanity` can stop them
+static SEARCHES: Mutex<Vec<(u32, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+static NEXT_SEARCH_ID: AtomicU32 = AtomicU32::new(1);
+
+// How many keypairs a worker generates between checks of the time budget
+const CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+// The same bound as `setThreadPoolSize`, far below where spawning fails
+const MAX_THREADS: f64 = 1024.0;
+
+// The budget of searches given neither a timeout nor a number of attempts, as
+// a pattern that no key matches would otherwise keep a thread busy forever
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+enum VanityError {
+  Cancelled(u64),
+  Exhausted(u64),
+}
+
+impl fmt::Display for VanityError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      VanityError::Cancelled(n) => write!(f, "vanity search was cancelled after {} attempts", n),
+      VanityError::Exhausted(n) => write!(f, "vanity search gave up after {} attempts", n),
+    }
+  }
+}
+
+impl VanityError {
+  fn code(&self) -> &'static str {
+    match self {
+      VanityError::Cancelled(_) => errors::VANITY_CANCELLED,
+      VanityError::Exhausted(_) => errors::VANITY_EXHAUSTED,
+    }
+  }
+}
+
+struct VanityTask {
+  id: u32,
+  pattern: Regex,
+  threads: usize,
+  timeout: Option<Duration>,
+  max_attempts: Option<u64>,
+  cancelled: Arc<AtomicBool>,
+}
+
+impl VanityTask {
+  fn search(&self) -> Result<(Keypair, u64), VanityError> {
+    let started = Instant::now();
+    let done = AtomicBool::new(false);
+    let attempts = AtomicU64::new(0);
+    let found: Mutex<Option<Keypair>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+      for _ in 0..self.threads {
+        scope.spawn(|| {
+          let mut local_attempts = 0u64;
+          while !done.load(Ordering::Relaxed) && !self.cancelled.load(Ordering::Relaxed) {
+            let n = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            if self.max_attempts.is_some_and(|max| n > max) {
+              break;
+            }
+            local_attempts += 1;
+            if local_attempts == CLOCK_CHECK_INTERVAL {
+              local_attempts = 0;
+              if self.timeout.is_some_and(|t| started.elapsed() >= t) {
+                break;
+              }
+            }
+
+            let keypair = Keypair::generate();
+            if self
+              .pattern
+              .is_match(&keypair.public.as_base64().wrap('@', ".ed25519"))
+            {
+              if !done.swap(true, Ordering::Relaxed) {
+                *found.lock().unwrap() = Some(keypair);
+              }
+              break;
+            }
+          }
+        });
+      }
+    });
+
+    let attempts = attempts.load(Ordering::Relaxed);
+    let attempts = self.max_attempts.map_or(attempts, |max| attempts.min(max));
+    match found.into_inner().unwrap() {
+      Some(keypair) => Ok((keypair, attempts)),
+      None if self.cancelled.load(Ordering::Relaxed) => Err(VanityError::Cancelled(attempts)),
+      None => Err(VanityError::Exhausted(attempts)),
+    }
+  }
+}
+
+impl Task for VanityTask {
+  type Output = (Keypair, u64);
+  type Error = VanityError;
+  type JsEvent = JsObject;
+
+  fn perform(&self) -> Result<(Keypair, u64), VanityError> {
+    let result = self.search();
+    SEARCHES.lock().unwrap().retain(|(id, _)| *id != self.id);
+    result
+  }
+
+  fn complete(
+    self,
+    mut cx: TaskContext,
+    result: Result<(Keypair, u64), VanityError>,
+  ) -> JsResult<JsObject> {
+    let (keypair, attempts) = result.or_else(|e| throw_code(&mut cx, e.code(), e.to_string()))?;
+
+    let out = cx.empty_object();
+    let keys = make_keys_obj(&mut cx, &keypair)?;
+    let attempts = cx.number(attempts as f64);
+    out.set(&mut cx, "keys", keys)?;
+    out.set(&mut cx, "attempts", attempts)?;
+    Ok(out)
+  }
+}
+
+// `opts.timeout` or `opts.maxAttempts`, which must be finite so that the
+// search ends
+fn budget_value(
+  cx: &mut FunctionContext,
+  opts: Handle<JsObject>,
+  field: &str,
+) -> NeonResult<Option<f64>> {
+  let msg = format!("opts.{} must be a number", field);
+  match get_field_opt::<JsNumber>(cx, opts, 1, field, &msg)?.map(|n| n.value()) {
+    Some(n) if !(n.is_finite() && n >= 0.0) => CodedError::range_error(
+      errors::INVALID_ARG_VALUE,
+      format!("opts.{} must be a finite number of at least 0", field),
+    )
+    .arg(1)
+    .throw(cx),
+    n => Ok(n),
+  }
+}
+
+// generateVanity: (prefix: string | RegExp, opts?: obj, cb: fn) => number
+pub fn neon_generate_vanity(mut cx: FunctionContext) -> JsResult<JsNumber> {
+  let argc = cx.len();
+  if argc < 2 {
+    return throw_code(
+      &mut cx,
+      errors::MISSING_ARGS,
+      "generateVanity requires at least two arguments: (prefix, cb)",
+    );
+  }
+
+  let pattern = {
+    let v = cx.argument::<JsValue>(0)?;
+    if let Some(s) = v.try_downcast::<JsString>() {
+      // Allow the prefix to be written as the start of a feed id
+      let prefix = s.value().trim_start_matches('@').to_string();
+      let is_base64 = prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/');
+      if prefix.is_empty() || !is_base64 {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          "vanity prefix must be a non-empty base64 string",
+        )
+        .arg(0)
+        .throw(&mut cx);
+      }
+      Regex::new(&format!("^@{}", regex::escape(&prefix))).unwrap() // infallible
+    } else if let Some(obj) = v.try_downcast::<JsObject>() {
+      let msg = "expected 1st argument to be a prefix string or a RegExp";
+      let source = get_field_opt::<JsString>(&mut cx, obj, 0, "source", msg)?
+        .or_throw_code(
+          &mut cx,
+          CodedError::type_error(errors::INVALID_ARG_TYPE, msg).arg(0),
+        )?
+        .value();
+      let flags = get_field_opt::<JsString>(&mut cx, obj, 0, "flags", "expected RegExp flags")?
+        .map(|f| f.value())
+        .unwrap_or_default();
+      // The others change how JS would match, in ways the regex crate can't
+      if flags.chars().any(|flag| flag != 'i') {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          format!(
+            "vanity RegExp flags must be empty or \"i\", got \"{}\"",
+            flags
+          ),
+        )
+        .arg(0)
+        .throw(&mut cx);
+      }
+      RegexBuilder::new(&source)
+        .case_insensitive(!flags.is_empty())
+        .build()
+        .or_else(|e| {
+          CodedError::range_error(
+            errors::INVALID_ARG_VALUE,
+            format!("invalid vanity regex: {}", e),
+          )
+          .arg(0)
+          .throw(&mut cx)
+        })?
+    } else {
+      return CodedError::type_error(
+        errors::INVALID_ARG_TYPE,
+        "expected 1st argument to be a prefix string or a RegExp",
+      )
+      .arg(0)
+      .throw(&mut cx);
+    }
+  };
+
+  let (threads, timeout, max_attempts) = if argc >= 3 {
+    let opts = cx.arg_as::<JsObject>(1, "expected 2nd argument to be an options object")?;
+    let threads =
+      get_field_opt::<JsNumber>(&mut cx, opts, 1, "threads", "opts.threads must be a number")?
+        .map(|n| n.value());
+    let timeout = budget_value(&mut cx, opts, "timeout")?.map(|n| Duration::from_millis(n as u64));
+    let max_attempts = budget_value(&mut cx, opts, "maxAttempts")?.map(|n| n as u64);
+    (threads, timeout, max_attempts)
+  } else {
+    (None, None, None)
+  };
+  let timeout = match (timeout, max_attempts) {
+    (None, None) => Some(DEFAULT_TIMEOUT),
+    (timeout, _) => timeout,
+  };
+
+  let threads = match threads {
+    Some(n) if (1.0..=MAX_THREADS).contains(&n) && n.fract() == 0.0 => n as usize,
+    Some(_) => {
+      return CodedError::range_error(
+        errors::INVALID_ARG_VALUE,
+        "opts.threads must be an integer from 1 to 1024",
+      )
+      .arg(1)
+      .throw(&mut cx)
+    }
+    None => thread::available_parallelism().map_or(1, |n| n.get()),
+  };
+
+  let cb = cx.arg_as::<JsFunction>(
+    argc - 1,
+    "expected a callback function given to `generateVanity`",
+  )?;
+
+  let id = NEXT_SEARCH_ID.fetch_add(1, Ordering::Relaxed);
+  let cancelled = Arc::new(AtomicBool::new(false));
+  SEARCHES.lock().unwrap().push((id, cancelled.clone()));
+
+  let task = VanityTask {
+    id,
+    pattern,
+    threads,
+    timeout,
+    max_attempts,
+    cancelled,
+  };
+  task.schedule(cb);
+  Ok(cx.number(id))
+}
+
+// cancelVanity: (id: number) => boolean
+pub fn neon_cancel_vanity(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+  let id = cx
+    .arg_as::<JsNumber>(0, "expected the number returned by `generateVanity`")?
+    .value() as u32;
+
+  let searches = SEARCHES.lock().unwrap();
+  let search = searches.iter().find(|(search_id, _)| *search_id == id);
+  if let Some((_, cancelled)) = search {
+    cancelled.store(true, Ordering::Relaxed);
+  }
+  Ok(cx.boolean(search.is_some()))
+}
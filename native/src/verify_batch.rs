@@ -0,0 +1,221 @@
+use super::errors::{self, CodedError, OptionCodeExt};
+use super::legacy_json;
+use super::pool;
+use super::sig::hmac_key_arg;
+use super::utils::{self, HandleExt, ValueExt};
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
+use neon::prelude::*;
+use sha2::{Digest, Sha512};
+use ssb_crypto::{NetworkKey as AuthKey, PublicKey, Signature};
+
+use std::convert::TryInto;
+use std::iter::once;
+
+// A signature with its parts decoded, for the equation R = sB - kA where
+// k = H(R || A || msg)
+struct Decoded {
+  r: EdwardsPoint,
+  s: Scalar,
+  a: EdwardsPoint,
+  k: Scalar,
+}
+
+// None if the encoding alone makes the signature invalid. ed25519-dalek
+// compares R by its encoding, so a non-canonical R is never valid
+fn decode(public: &PublicKey, sig: &Signature, msg: &[u8]) -> Option<Decoded> {
+  let r_bytes = CompressedEdwardsY::from_slice(&sig.0[..32]);
+  let r = r_bytes.decompress().filter(|r| r.compress() == r_bytes)?;
+  let s = Scalar::from_canonical_bytes(sig.0[32..].try_into().unwrap())?;
+  let a = CompressedEdwardsY(public.0).decompress()?;
+  let mut h = Sha512::new();
+  h.update(&sig.0[..32]);
+  h.update(public.0);
+  h.update(msg);
+  let k = Scalar::from_hash(h);
+  Some(Decoded { r, s, a, k })
+}
+
+// The same check as ed25519-dalek's `verify`, which `verifyObj` uses
+fn verify_one(d: &Decoded) -> bool {
+  EdwardsPoint::vartime_double_scalar_mul_basepoint(&d.k, &-d.a, &d.s) == d.r
+}
+
+// The random linear combination of all the equations that ed25519-dalek's
+// `verify_batch` checks, times the cofactor 8. Without it the combination
+// depends on the random factors whenever A or R has a small order component,
+// and ruling those out costs a scalar multiplication per signature, more than
+// the batch saves. With it such components are ignored, so the batch may
+// accept a signature that `verify_one` rejects, but only one that no honest
+// signer produces
+fn verify_all(items: &[&Decoded]) -> bool {
+  let mut random = vec![0u8; 16 * items.len()];
+  if getrandom::getrandom(&mut random).is_err() {
+    return false;
+  }
+  let zs = random
+    .chunks(16)
+    .map(|z| Scalar::from(u128::from_le_bytes(z.try_into().unwrap())))
+    .collect::<Vec<_>>();
+  let b: Scalar = items.iter().zip(&zs).map(|(d, z)| z * d.s).sum();
+  let scalars = once(-b)
+    .chain(zs.iter().copied())
+    .chain(items.iter().zip(&zs).map(|(d, z)| z * d.k));
+  let points = once(ED25519_BASEPOINT_POINT)
+    .chain(items.iter().map(|d| d.r))
+    .chain(items.iter().map(|d| d.a));
+  EdwardsPoint::vartime_multiscalar_mul(scalars, points)
+    .mul_by_cofactor()
+    .is_identity()
+}
+
+// The signature and the signed JSON of an object, read like `verifyObj` does,
+// or None if it has no usable signature
+fn signed_parts<'a>(
+  cx: &mut FunctionContext<'a>,
+  v: Handle<'a, JsValue>,
+) -> NeonResult<Option<(Signature, Vec<u8>)>> {
+  let obj = match v.try_downcast::<JsObject>() {
+    Some(obj) => obj,
+    None => return Ok(None),
+  };
+  let sig: Handle<JsValue> = obj.get(cx, "signature")?;
+  let signature = sig.try_downcast::<JsString>().and_then(|sig| {
+    let mut sig = sig.value();
+    sig.truncate(sig.rfind(".sig.ed25519")?);
+    Signature::from_base64(&sig)
+  });
+  let signature = match signature {
+    Some(signature) => signature,
+    None => return Ok(None),
+  };
+
+  let msg = legacy_json::stringify_unsigned(cx, obj)?.into_bytes();
+  Ok(Some((signature, msg)))
+}
+
+// The objects of a batch, each with its key, read on the main thread
+struct Batch {
+  items: Vec<Option<(PublicKey, Signature, Vec<u8>)>>,
+  hmac_key: Option<AuthKey>,
+}
+
+impl Batch {
+  fn verify(&self) -> Vec<bool> {
+    let decoded = self
+      .items
+      .iter()
+      .map(|item| {
+        let (public, sig, msg) = item.as_ref()?;
+        match &self.hmac_key {
+          None => decode(public, sig, msg),
+          Some(hmac_key) => decode(public, sig, &hmac_key.authenticate(msg).0),
+        }
+      })
+      .collect::<Vec<_>>();
+
+    // Usually everything is valid, and only one check is needed. Otherwise
+    // find out which ones aren't
+    let batch = decoded.iter().flatten().collect::<Vec<_>>();
+    let all_valid = verify_all(&batch);
+    decoded
+      .iter()
+      .map(|d| d.as_ref().is_some_and(|d| all_valid || verify_one(d)))
+      .collect()
+  }
+}
+
+fn batch_args(cx: &mut FunctionContext, argc: i32, name: &str) -> NeonResult<Batch> {
+  if argc < 3 {
+    return errors::throw_code(
+      cx,
+      errors::MISSING_ARGS,
+      format!(
+        "{} requires three arguments: (keysOrIds, hmacKey, objs)",
+        name
+      ),
+    );
+  }
+  let expected_key = "expected 1st argument to be keys or ids, or an array of them";
+  let decode_key = "cannot base64 decode the public key";
+
+  let objs = cx
+    .argument::<JsValue>(2)?
+    .try_downcast::<JsArray>()
+    .or_throw_code(
+      cx,
+      CodedError::type_error(
+        errors::INVALID_ARG_TYPE,
+        "expected 3rd argument to be an array of objects",
+      )
+      .arg(2),
+    )?
+    .to_vec(cx)?;
+
+  let keys = cx.argument::<JsValue>(0)?;
+  let publics = match keys.try_downcast::<JsArray>() {
+    Some(keys) => {
+      let keys = keys.to_vec(cx)?;
+      if keys.len() != objs.len() {
+        return CodedError::range_error(
+          errors::INVALID_ARG_VALUE,
+          format!("expected as many keys or ids as objects in `{}`", name),
+        )
+        .arg(0)
+        .throw(cx);
+      }
+      keys
+        .into_iter()
+        .map(|key| utils::public_key_value(cx, key, 0, expected_key, decode_key))
+        .collect::<NeonResult<Vec<_>>>()?
+    }
+    None => vec![utils::public_key_value(cx, keys, 0, expected_key, decode_key)?; objs.len()],
+  };
+
+  let hmac_key = if cx.argument::<JsValue>(1)?.is_truthy(cx) {
+    Some(hmac_key_arg(cx, 1, "2nd")?)
+  } else {
+    None
+  };
+
+  let mut items = Vec::with_capacity(objs.len());
+  for (obj, public) in objs.into_iter().zip(publics) {
+    items.push(signed_parts(cx, obj)?.map(|(sig, msg)| (public, sig, msg)));
+  }
+  Ok(Batch { items, hmac_key })
+}
+
+fn results_to_array<'a>(cx: &mut impl Context<'a>, results: &[bool]) -> JsResult<'a, JsArray> {
+  let array = JsArray::new(cx, results.len() as u32);
+  for (i, valid) in results.iter().enumerate() {
+    let valid = cx.boolean(*valid);
+    array.set(cx, i as u32, valid)?;
+  }
+  Ok(array)
+}
+
+// verifyObjBatch: (keysOrIds: Array<obj | string> | obj | string, hmac_key: Buffer | string | null, objs: Array<obj>) => Array<boolean>
+pub fn neon_verify_obj_batch(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let argc = cx.len();
+  let batch = batch_args(&mut cx, argc, "verifyObjBatch")?;
+  let results = batch.verify();
+  results_to_array(&mut cx, &results)
+}
+
+// verifyObjBatchAsync: (keysOrIds, hmac_key, objs, cb: (err, Array<boolean>) => void) => void
+pub fn neon_verify_obj_batch_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let callback = pool::callback_arg(&mut cx, "verifyObjBatchAsync")?;
+  let argc = cx.len() - 1;
+  let batch = batch_args(&mut cx, argc, "verifyObjBatchAsync")?;
+  let this = cx.undefined().upcast();
+  pool::spawn(
+    &mut cx,
+    this,
+    callback,
+    move || batch.verify(),
+    |cx, _, results| Ok(results_to_array(cx, &results)?.upcast()),
+  );
+  Ok(cx.undefined())
+}
@@ -0,0 +1,61 @@
+use ssb_crypto::{AsBytes, Keypair};
+use zeroize::Zeroizing;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Buffers and strings holding secret key material, zeroed when dropped.
+// Anything secret should be built directly inside one of these, because a
+// `Vec` or `String` that grows leaves its old allocation behind unwiped
+pub type SecretBytes = Zeroizing<Vec<u8>>;
+pub type SecretString = Zeroizing<String>;
+
+// Like `fs::read_to_string`, sizing the buffer up front so it never grows
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<SecretString> {
+  let mut file = File::open(path)?;
+  let len = file.metadata().map(|m| m.len() as usize).unwrap_or(0);
+  let mut out = Zeroizing::new(String::with_capacity(len + 1));
+  file.read_to_string(&mut out)?;
+  Ok(out)
+}
+
+// Like `base64::decode_config`, into a buffer that is wiped on drop
+pub fn decode_base64(s: &str, config: base64::Config) -> Option<SecretBytes> {
+  // Big enough that decoding never reallocates
+  let mut buf = Zeroizing::new(Vec::with_capacity(s.len() + 8));
+  base64::decode_config_buf(s, config, &mut buf).ok()?;
+  Some(buf)
+}
+
+// Like `base64::encode_config` followed by `suffix`, but appending the
+// suffix doesn't reallocate and leave a copy of the encoding behind
+pub fn encode_base64(bytes: &[u8], config: base64::Config, suffix: &str) -> SecretString {
+  let mut buf = Zeroizing::new(vec![0u8; bytes.len() * 4 / 3 + 4]);
+  let len = base64::encode_config_slice(bytes, config, &mut buf);
+  let mut out = Zeroizing::new(String::with_capacity(len + suffix.len()));
+  out.push_str(std::str::from_utf8(&buf[..len]).unwrap()); // base64 is ascii
+  out.push_str(suffix);
+  out
+}
+
+// Like `Keypair::from_base64`, which decodes into a stack buffer that it
+// doesn't wipe
+pub fn keypair_from_base64(s: &str) -> Option<Keypair> {
+  let s = match s.rfind(".ed25519") {
+    Some(idx) => &s[..idx],
+    None => s,
+  };
+  // 64 bytes are 86 base64 characters plus 2 of padding
+  if s.len() != 88 || s.trim_end_matches('=').len() != 86 {
+    return None;
+  }
+  let mut buf = Zeroizing::new([0u8; Keypair::SIZE]);
+  base64::decode_config_slice(s, base64::STANDARD, &mut buf[..]).ok()?;
+  Keypair::from_slice(&buf[..])
+}
+
+// Like `keypair.as_base64().with_suffix(suffix)`, which leaves copies
+pub fn keypair_to_base64(keypair: &Keypair, suffix: &str) -> SecretString {
+  encode_base64(keypair.as_bytes(), base64::STANDARD, suffix)
+}
@@ -0,0 +1,93 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('the async variants agree with the sync functions', async function (t) {
+  let keys = ssbKeys.generate();
+  let hmacKey = crypto.randomBytes(32);
+
+  let sig = await ssbKeys.signAsync(keys, hmacKey, 'hello');
+  t.equal(sig, ssbKeys.sign(keys, hmacKey, 'hello'));
+  t.true(await ssbKeys.verifyAsync(keys, sig, hmacKey, 'hello'));
+  t.false(await ssbKeys.verifyAsync(keys, sig, 'hello'));
+
+  let obj = {foo: 'bar'};
+  let signed = await ssbKeys.signObjAsync(keys, obj);
+  t.deepEqual(signed, ssbKeys.signObj(keys, obj));
+  t.notOk(obj.signature, 'the given object is not modified');
+  t.true(await ssbKeys.verifyObjAsync(keys, signed));
+  t.deepEqual(await ssbKeys.verifyObjBatchAsync(keys, null, [signed, obj]), [true, false]);
+
+  t.equal(await ssbKeys.hashAsync('hello'), ssbKeys.hash('hello'));
+  t.equal(await ssbKeys.hashAsync('68656c6c6f', 'hex'), ssbKeys.hash('hello'));
+
+  let boxed = await ssbKeys.boxAsync({hello: 'world'}, [keys]);
+  t.deepEqual(await ssbKeys.unboxAsync(boxed, keys), {hello: 'world'});
+  t.equal(await ssbKeys.unboxAsync(boxed, ssbKeys.generate()), undefined);
+  let msgKey = await ssbKeys.unboxKeyAsync(boxed, keys);
+  t.deepEqual(msgKey, ssbKeys.unboxKey(boxed, keys));
+  t.deepEqual(await ssbKeys.unboxBodyAsync(boxed, msgKey), {hello: 'world'});
+
+  let key = crypto.randomBytes(32);
+  let secret = await ssbKeys.secretBoxAsync({hello: 'world'}, key);
+  t.deepEqual(await ssbKeys.secretUnboxAsync(secret, key), {hello: 'world'});
+  t.equal(await ssbKeys.secretBoxAsync(null, key), undefined);
+  t.end();
+});
+
+tape('the async variants reject instead of throwing', async function (t) {
+  try {
+    await ssbKeys.signObjAsync(ssbKeys.generate(), 'not an object');
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+  }
+  try {
+    await ssbKeys.secretUnboxAsync(Buffer.alloc(4), crypto.randomBytes(32));
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_SSB_KEYS_DECRYPT_FAILED');
+  }
+  t.end();
+});
+
+tape('the thread pool size is configurable', async function (t) {
+  let size = ssbKeys.getThreadPoolSize();
+  t.true(size >= 1);
+  ssbKeys.setThreadPoolSize(2);
+  t.equal(ssbKeys.getThreadPoolSize(), 2);
+
+  let keys = ssbKeys.generate();
+  let objs = [];
+  for (let i = 0; i < 50; i++) objs.push({seq: i});
+  let signed = await Promise.all(objs.map((obj) => ssbKeys.signObjAsync(keys, obj)));
+  t.deepEqual(signed, objs.map((obj) => ssbKeys.signObj(keys, obj)));
+
+  t.throws(() => ssbKeys.setThreadPoolSize(0), /from 1 to 1024/);
+  ssbKeys.setThreadPoolSize(size);
+  t.end();
+});
+
+tape('callback functions return a promise without a callback', async function (t) {
+  let filename = tmpPath('secret');
+  let keys = await ssbKeys.create(filename);
+  t.deepEqual(await ssbKeys.load(filename), keys);
+  t.deepEqual(await ssbKeys.loadOrCreate(filename), keys);
+  t.equal((await ssbKeys.generateMany(3)).length, 3);
+
+  let encrypted = tmpPath('secret');
+  let encKeys = await ssbKeys.createEncrypted(encrypted, 'pass');
+  await ssbKeys.changePassphrase(encrypted, 'pass', 'word');
+  t.deepEqual(await ssbKeys.loadEncrypted(encrypted, 'word'), encKeys);
+
+  let search = ssbKeys.generateVanity('@a');
+  t.equal(typeof search.id, 'number');
+  t.ok((await search).keys.id.startsWith('@a'));
+  t.end();
+});
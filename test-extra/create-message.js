@@ -0,0 +1,98 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+
+// How ssb-validate computes message ids
+function jsId(value) {
+  return '%' + jsKeys.hash(JSON.stringify(value, null, 2));
+}
+
+function code(fn) {
+  try {
+    fn();
+  } catch (err) {
+    return err.code;
+  }
+}
+
+tape('createMessage signs the same message as ssb-keys', function (t) {
+  let keys = ssbKeys.generate();
+  let hmacKey = crypto.randomBytes(32);
+  let content = {type: 'post', text: 'héllo'};
+  let first = ssbKeys.createMessage(keys, hmacKey, null, content, 1600000000000);
+  let expected = jsKeys.signObj(keys, hmacKey, {
+    previous: null,
+    author: keys.id,
+    sequence: 1,
+    timestamp: 1600000000000,
+    hash: 'sha256',
+    content,
+  });
+  t.deepEqual(first.value, expected);
+  t.deepEqual(Object.keys(first.value), Object.keys(expected));
+  t.equal(first.key, jsId(expected));
+  t.deepEqual(first.state, ssbKeys.validate(null, hmacKey, first.value));
+
+  let second = ssbKeys.createMessage(keys, hmacKey, first.state, {type: 'vote'}, 1600000000001);
+  t.equal(second.value.previous, first.key);
+  t.equal(second.value.sequence, 2);
+  t.deepEqual(second.state, ssbKeys.validate(first.state, hmacKey, second.value));
+  t.end();
+});
+
+tape('createMessage keeps timestamps increasing', function (t) {
+  let keys = ssbKeys.generate();
+  let before = Date.now();
+  let first = ssbKeys.createMessage(keys, null, null, {type: 'post'});
+  t.true(first.value.timestamp >= before && first.value.timestamp <= Date.now());
+
+  let future = ssbKeys.createMessage(keys, null, first.state, {type: 'post'}, Date.now() + 60000);
+  let next = ssbKeys.createMessage(keys, null, future.state, {type: 'post'});
+  t.equal(next.value.timestamp, future.value.timestamp + 1);
+
+  let err = code(() => ssbKeys.createMessage(keys, null, next.state, {type: 'post'}, next.value.timestamp));
+  t.equal(err, 'ERR_INVALID_ARG_VALUE');
+  t.end();
+});
+
+tape('createMessage checks the content', function (t) {
+  let keys = ssbKeys.generate();
+  t.equal(code(() => ssbKeys.createMessage(keys, null, null, {type: 'ab'})), 'ERR_SSB_KEYS_INVALID_MESSAGE');
+  t.equal(code(() => ssbKeys.createMessage(keys, null, null, 'plain text')), 'ERR_SSB_KEYS_INVALID_MESSAGE');
+  let big = {type: 'post', text: 'x'.repeat(8000)};
+  t.equal(code(() => ssbKeys.createMessage(keys, null, null, big)), 'ERR_SSB_KEYS_INVALID_MESSAGE');
+
+  let boxed = ssbKeys.box({type: 'post'}, [keys]);
+  t.equal(ssbKeys.createMessage(keys, null, null, boxed).value.content, boxed);
+  t.end();
+});
+
+tape('createMessage checks its arguments', function (t) {
+  let keys = ssbKeys.generate();
+  let other = ssbKeys.createMessage(ssbKeys.generate(), null, null, {type: 'post'});
+  t.equal(code(() => ssbKeys.createMessage(keys, null, null)), 'ERR_MISSING_ARGS');
+  t.equal(code(() => ssbKeys.createMessage(keys, null, other.state, {type: 'post'})), 'ERR_INVALID_ARG_VALUE');
+  t.equal(code(() => ssbKeys.createMessage(keys, null, null, {type: 'post'}, 'now')), 'ERR_INVALID_ARG_TYPE');
+  t.end();
+});
+
+tape('createMessageBatch appends a run of messages', function (t) {
+  let keys = ssbKeys.generate();
+  let contents = [{type: 'post', text: 'a'}, {type: 'post', text: 'b'}, {type: 'vote'}];
+  let msgs = ssbKeys.createMessageBatch(keys, null, null, contents, [10, 20, 30]);
+  t.deepEqual(msgs.map((msg) => msg.value.timestamp), [10, 20, 30]);
+  t.deepEqual(msgs.map((msg) => msg.key), msgs.map((msg) => jsId(msg.value)));
+  let values = msgs.map((msg) => msg.value);
+  t.deepEqual(ssbKeys.validateBatch(null, null, values), msgs[2].state);
+
+  let more = ssbKeys.createMessageBatch(keys, null, msgs[2].state, [{type: 'post'}]);
+  t.equal(more[0].value.previous, msgs[2].key);
+  t.deepEqual(ssbKeys.createMessageBatch(keys, null, null, []), []);
+
+  let err = code(() => ssbKeys.createMessageBatch(keys, null, null, contents, [10, 30, 20]));
+  t.equal(err, 'ERR_INVALID_ARG_VALUE');
+  err = code(() => ssbKeys.createMessageBatch(keys, null, null, contents, [10]));
+  t.equal(err, 'ERR_INVALID_ARG_VALUE');
+  t.end();
+});
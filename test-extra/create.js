@@ -0,0 +1,96 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+// The layout of keyfiles from ssb-keys 8.2.0, whose keys object is in the
+// order `generate` returns it
+function jsLayout(keys, legacy) {
+  let json = {curve: keys.curve, public: keys.public, private: keys.private, id: keys.id};
+  return [
+    '# WARNING: Never show this to anyone.',
+    '# WARNING: Never edit it or use it on multiple devices at once.',
+    '#',
+    '# This is your SECRET, it gives you magical powers. With your secret you can',
+    '# sign your messages so that your friends can verify that the messages came',
+    '# from you. If anyone learns your secret, they can use it to impersonate you.',
+    '#',
+    '# If you use this secret on more than one device you will create a fork and',
+    '# your friends will stop replicating your content.',
+    '#',
+    legacy ? keys.private : JSON.stringify(json, null, 2),
+    '#',
+    "# The only part of this file that's safe to share is your public name:",
+    '#',
+    '#   ' + keys.id,
+  ].join('\n');
+}
+
+tape('createSync writes keyfiles byte for byte like ssb-keys', function (t) {
+  for (let legacy of [false, true]) {
+    let filename = tmpPath('secret');
+    let keys = ssbKeys.createSync(filename, 'ed25519', legacy);
+    t.equal(fs.readFileSync(filename, 'utf8'), jsLayout(keys, legacy), 'legacy: ' + legacy);
+  }
+  t.end();
+});
+
+tape('create writes keyfiles byte for byte like ssb-keys', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.create(filename, 'ed25519', true, function (err, keys) {
+    t.error(err);
+    t.equal(fs.readFileSync(filename, 'utf8'), jsLayout(keys, true));
+    let jsonFile = tmpPath('secret');
+    ssbKeys.create(jsonFile, function (err, keys) {
+      t.error(err);
+      t.equal(fs.readFileSync(jsonFile, 'utf8'), jsLayout(keys, false));
+      t.end();
+    });
+  });
+});
+
+tape('createSync creates missing parent directories', function (t) {
+  let filename = tmpPath('a/b/secret');
+  let keys = ssbKeys.createSync(filename, 'ed25519');
+  t.deepEqual(ssbKeys.loadSync(filename), keys);
+  t.deepEqual(jsKeys.loadSync(filename), keys, 'ssb-keys can read it');
+  t.end();
+});
+
+tape('createSync with isLegacy writes the legacy layout', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename, null, true);
+  t.equal(fs.readFileSync(filename, 'utf8'), jsLayout(keys, true));
+  t.deepEqual(ssbKeys.loadSync(filename), keys);
+  t.deepEqual(jsKeys.loadSync(filename), keys, 'ssb-keys can read it');
+  t.end();
+});
+
+tape('loadSync reads legacy keyfiles written by ssb-keys', function (t) {
+  let filename = tmpPath('secret');
+  let keys = jsKeys.createSync(filename, 'ed25519', true);
+  t.deepEqual(ssbKeys.loadSync(filename), keys);
+  t.end();
+});
+
+tape('create supports (path, curve, isLegacy, cb)', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.create(filename, 'ed25519', true, function (err, keys) {
+    t.error(err);
+    t.notOk(fs.readFileSync(filename, 'utf8').includes('"curve"'), 'legacy');
+    t.deepEqual(ssbKeys.loadSync(filename), keys);
+    t.end();
+  });
+});
+
+tape('create and createSync validate the curve', function (t) {
+  t.throws(() => ssbKeys.createSync(tmpPath('secret'), 'secp256k1'), /only supports: ed25519/);
+  t.throws(() => ssbKeys.create(tmpPath('secret'), 42, () => {}), TypeError);
+  t.end();
+});
@@ -0,0 +1,103 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('createEncryptedSync and loadEncryptedSync roundtrip', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createEncryptedSync(filename, 'correct horse');
+  let contents = fs.readFileSync(filename, 'utf8');
+  t.ok(contents.startsWith('SSB-ENCRYPTED-KEYFILE v1\n'), 'has the header');
+  t.notOk(contents.includes(keys.private), 'does not leak the private key');
+  t.deepEqual(ssbKeys.loadEncryptedSync(filename, 'correct horse'), keys);
+  t.end();
+});
+
+tape('loadEncryptedSync rejects a wrong passphrase', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createEncryptedSync(filename, 'correct horse');
+  t.throws(() => ssbKeys.loadEncryptedSync(filename, 'battery staple'), /wrong passphrase/);
+  t.end();
+});
+
+tape('loadSync asks for a passphrase on encrypted keyfiles', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createEncryptedSync(filename, 'correct horse');
+  t.throws(() => ssbKeys.loadSync(filename), /encrypted keyfile, passphrase required/);
+  t.end();
+});
+
+tape('changePassphrase re-encrypts the same keys', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createEncrypted(filename, 'old one', function (err, keys) {
+    t.error(err);
+    ssbKeys.changePassphrase(filename, 'old one', 'new one', function (err, keys2) {
+      t.error(err);
+      t.deepEqual(keys2, keys);
+      t.throws(() => ssbKeys.loadEncryptedSync(filename, 'old one'), /wrong passphrase/);
+      ssbKeys.loadEncrypted(filename, 'new one', function (err, keys3) {
+        t.error(err);
+        t.deepEqual(keys3, keys);
+        t.end();
+      });
+    });
+  });
+});
+
+tape('loadEncryptedSync refuses kdf costs above the maximum', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createEncryptedSync(filename, 'correct horse');
+  let contents = fs.readFileSync(filename, 'utf8');
+  for (let [name, value] of [['m', 4 * 1024 * 1024], ['t', 1000000], ['p', 1024]]) {
+    let hostile = contents.replace(new RegExp('"' + name + '": \\d+'), '"' + name + '": ' + value);
+    fs.writeFileSync(filename, hostile);
+    try {
+      ssbKeys.loadEncryptedSync(filename, 'correct horse');
+      t.fail('should have thrown for ' + name);
+    } catch (err) {
+      t.equal(err.code, 'ERR_KEYFILE_CORRUPT', err.message);
+      t.equal(err.path, filename);
+    }
+  }
+  t.end();
+});
+
+tape('loadEncryptedSync checks the id against the decrypted key', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createEncryptedSync(filename, 'correct horse');
+  let other = ssbKeys.generate();
+  fs.writeFileSync(filename, fs.readFileSync(filename, 'utf8').replace(keys.id, other.id));
+  try {
+    ssbKeys.loadEncryptedSync(filename, 'correct horse');
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_KEYFILE_CORRUPT', err.message);
+    t.equal(err.path, filename);
+  }
+  t.end();
+});
+
+tape('encrypted keyfile errors have codes', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createEncryptedSync(filename, 'correct horse');
+  try {
+    ssbKeys.loadEncryptedSync(filename, 'battery staple');
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_SSB_KEYS_DECRYPT_FAILED');
+  }
+  let plain = tmpPath('secret');
+  ssbKeys.createSync(plain);
+  ssbKeys.loadEncrypted(plain, 'correct horse', function (err) {
+    t.equal(err.code, 'ERR_KEYFILE_NOT_ENCRYPTED');
+    ssbKeys.loadEncrypted(tmpPath('missing'), 'correct horse', function (err) {
+      t.equal(err.code, 'ERR_KEYFILE_NOT_FOUND');
+      t.end();
+    });
+  });
+});
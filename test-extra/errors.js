@@ -0,0 +1,137 @@
+let tape = require('tape');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('a bad private key throws ERR_SSB_KEYS_BAD_PRIVATE_KEY', function (t) {
+  try {
+    ssbKeys.sign('not a key', 'hello');
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_SSB_KEYS_BAD_PRIVATE_KEY');
+    t.equal(err.argIndex, 0);
+  }
+  t.end();
+});
+
+tape('a wrong argument type throws ERR_INVALID_ARG_TYPE', function (t) {
+  let keys = ssbKeys.generate();
+  try {
+    ssbKeys.signObj(keys, 42);
+    t.fail('should have thrown');
+  } catch (err) {
+    t.ok(err instanceof TypeError);
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+    t.equal(err.argIndex, 1);
+  }
+  t.end();
+});
+
+tape('a signature without suffix throws ERR_SIG_SUFFIX', function (t) {
+  let keys = ssbKeys.generate();
+  let sig = ssbKeys.sign(keys, 'hello').replace('.sig.ed25519', '');
+  try {
+    ssbKeys.verify(keys, sig, 'hello');
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_SIG_SUFFIX');
+    t.equal(err.argIndex, 1);
+  }
+  t.end();
+});
+
+tape('a weak seed throws ERR_SSB_KEYS_WEAK_SEED', function (t) {
+  try {
+    ssbKeys.generate('ed25519', Buffer.alloc(32));
+    t.fail('should have thrown');
+  } catch (err) {
+    t.ok(err instanceof RangeError);
+    t.equal(err.code, 'ERR_SSB_KEYS_WEAK_SEED');
+    t.equal(err.argIndex, 1);
+  }
+  t.end();
+});
+
+tape('loadSync of a missing keyfile throws ERR_KEYFILE_NOT_FOUND', function (t) {
+  let filename = tmpPath('secret');
+  try {
+    ssbKeys.loadSync(filename);
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_KEYFILE_NOT_FOUND');
+    t.equal(err.path, filename);
+  }
+  t.end();
+});
+
+tape('load of a missing keyfile calls back with ERR_KEYFILE_NOT_FOUND', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.load(filename, function (err, keys) {
+    t.ok(err);
+    t.equal(err.code, 'ERR_KEYFILE_NOT_FOUND');
+    t.equal(err.path, filename);
+    t.notOk(keys);
+    t.end();
+  });
+});
+
+tape('createSync over an existing keyfile throws ERR_KEYFILE_EXISTS', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  try {
+    ssbKeys.createSync(filename);
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_KEYFILE_EXISTS');
+  }
+  t.end();
+});
+
+tape('errors of the extra APIs have codes', function (t) {
+  function caught(fn) {
+    try {
+      fn();
+    } catch (err) {
+      return [err.code, err.argIndex];
+    }
+  }
+  let keys = ssbKeys.generate();
+  t.deepEqual(caught(() => ssbKeys.deriveKeys(keys, "m/44'/1")), ['ERR_INVALID_ARG_VALUE', 1]);
+  t.deepEqual(caught(() => ssbKeys.createEncryptedSync(tmpPath('secret'), '')), ['ERR_INVALID_ARG_VALUE', 1]);
+  t.deepEqual(caught(() => ssbKeys.fromOpenSSH('ssh-ed25519 AAAA')), ['ERR_SSB_KEYS_BAD_ENCODING', 0]);
+  t.deepEqual(caught(() => ssbKeys.fromOpenSSH('x', 42)), ['ERR_INVALID_ARG_TYPE', 1]);
+  t.deepEqual(caught(() => ssbKeys.toOpenSSH(keys, {comment: 42})), ['ERR_INVALID_ARG_TYPE', 1]);
+  t.deepEqual(caught(() => ssbKeys.toOpenSSH(keys, {passphrase: ''})), ['ERR_INVALID_ARG_VALUE', 1]);
+  t.deepEqual(caught(() => ssbKeys.verifyRotation(42)), ['ERR_INVALID_ARG_TYPE', 0]);
+  t.deepEqual(caught(() => ssbKeys.verifyRotation({type: 'ssb-identity-rotation'})), ['ERR_SSB_KEYS_BAD_ENCODING', 0]);
+  t.deepEqual(caught(() => ssbKeys.generateVanity('!!', () => {})), ['ERR_INVALID_ARG_VALUE', 0]);
+  t.deepEqual(caught(() => ssbKeys.generateVanity(/a/, {timeout: 'soon'}, () => {})), ['ERR_INVALID_ARG_TYPE', 1]);
+
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  t.deepEqual(caught(() => ssbKeys.loadSync(filename, {permissions: 'maybe'})), ['ERR_INVALID_ARG_VALUE', 1]);
+  let err = caught(() => ssbKeys.rotateIdentity(tmpPath('secret')));
+  t.deepEqual(err, ['ERR_KEYFILE_NOT_FOUND', undefined]);
+  t.deepEqual(caught(() => ssbKeys.auditKeyfile(tmpPath('secret'))), ['ERR_KEYFILE_NOT_FOUND', undefined]);
+
+  let dir = path.dirname(tmpPath('profiles'));
+  ssbKeys.addIdentity(dir, 'work');
+  t.deepEqual(caught(() => ssbKeys.addIdentity(dir, 'work')), ['ERR_INVALID_ARG_VALUE', 1]);
+  t.deepEqual(caught(() => ssbKeys.setDefault(dir, '@nope.ed25519')), ['ERR_INVALID_ARG_VALUE', 1]);
+  t.end();
+});
+
+tape('a cancelled vanity search calls back with ERR_SSB_KEYS_VANITY_CANCELLED', function (t) {
+  let id = ssbKeys.generateVanity('AAAAAAAAAA', {threads: 1}, function (err) {
+    t.equal(err.code, 'ERR_SSB_KEYS_VANITY_CANCELLED');
+    ssbKeys.generateVanity('AAAAAAAAAA', {threads: 1, maxAttempts: 10}, function (err) {
+      t.equal(err.code, 'ERR_SSB_KEYS_VANITY_EXHAUSTED');
+      t.end();
+    });
+  });
+  ssbKeys.cancelVanity(id);
+});
@@ -0,0 +1,47 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let ssbKeys = require('../');
+
+tape('generateMany(n) creates n distinct keys', function (t) {
+  ssbKeys.generateMany(50, function (err, keys) {
+    t.error(err);
+    t.equal(keys.length, 50);
+    t.equal(new Set(keys.map((k) => k.id)).size, 50, 'all distinct');
+    t.end();
+  });
+});
+
+tape('generateMany(seeds) matches generate(curve, seed)', function (t) {
+  let seeds = [crypto.randomBytes(32), crypto.randomBytes(32)];
+  ssbKeys.generateMany(seeds, function (err, keys) {
+    t.error(err);
+    t.deepEqual(keys, seeds.map((s) => ssbKeys.generate('ed25519', s)));
+    t.end();
+  });
+});
+
+tape('generateMany validates seeds synchronously', function (t) {
+  t.throws(
+    () => ssbKeys.generateMany([crypto.randomBytes(31)], () => {}),
+    /seed #0 given to `generateMany` must be 32 bytes/
+  );
+  t.throws(() => ssbKeys.generateMany(-1, () => {}), /count/);
+  for (let n of [-1, 1.5, 100001, 2 ** 32]) {
+    try {
+      ssbKeys.generateMany(n, () => t.fail('should not have started'));
+      t.fail('should have thrown');
+    } catch (err) {
+      t.ok(err instanceof RangeError);
+      t.equal(err.code, 'ERR_INVALID_ARG_VALUE', 'n: ' + n);
+    }
+  }
+  try {
+    ssbKeys.generateMany(new Array(100001).fill(crypto.randomBytes(32)), () => t.fail('should not have started'));
+    t.fail('should have thrown');
+  } catch (err) {
+    t.ok(err instanceof RangeError);
+    t.equal(err.code, 'ERR_INVALID_ARG_VALUE', '100001 seeds');
+    t.equal(err.argIndex, 0);
+  }
+  t.end();
+});
@@ -0,0 +1,31 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let ssbKeys = require('../');
+
+tape('generate accepts seeds as Uint8Array, hex and base64', function (t) {
+  let seed = crypto.randomBytes(32);
+  let keys = ssbKeys.generate('ed25519', seed);
+  t.deepEqual(ssbKeys.generate('ed25519', new Uint8Array(seed)), keys, 'Uint8Array');
+  t.deepEqual(ssbKeys.generate('ed25519', seed.toString('hex')), keys, 'hex');
+  t.deepEqual(ssbKeys.generate('ed25519', seed.toString('base64')), keys, 'base64');
+  t.deepEqual(ssbKeys.generate(null, seed), keys, 'default curve');
+  t.end();
+});
+
+tape('generate validates seed and curve types and lengths', function (t) {
+  t.throws(() => ssbKeys.generate('ed25519', crypto.randomBytes(31)), RangeError);
+  t.throws(() => ssbKeys.generate('ed25519', 'not a seed!'), TypeError);
+  t.throws(() => ssbKeys.generate('ed25519', 42), TypeError);
+  t.throws(() => ssbKeys.generate(25519, crypto.randomBytes(32)), TypeError);
+  t.end();
+});
+
+tape('generate rejects low-entropy seeds unless allowed', function (t) {
+  let zeros = Buffer.alloc(32);
+  t.throws(() => ssbKeys.generate('ed25519', zeros), /low-entropy/);
+  let counting = Buffer.from(Array.from({length: 32}, (_, i) => i));
+  t.throws(() => ssbKeys.generate('ed25519', counting), /low-entropy/);
+  let keys = ssbKeys.generate('ed25519', zeros, {allowWeakSeed: true});
+  t.equal(keys.curve, 'ed25519', 'opt-in works');
+  t.end();
+});
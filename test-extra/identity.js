@@ -0,0 +1,58 @@
+let tape = require('tape');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('openIdentity returns a handle without the private key', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  let identity = ssbKeys.openIdentity(filename);
+  t.equal(identity.id, keys.id);
+  t.equal(identity.public, keys.public);
+  t.equal(identity.private, undefined);
+  t.notOk(JSON.stringify(identity).includes(keys.private));
+  t.end();
+});
+
+tape('identity methods match the functions taking a keys object', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  let identity = ssbKeys.openIdentity(filename);
+
+  t.equal(identity.sign('hello'), ssbKeys.sign(keys, 'hello'));
+  let obj = identity.signObj({foo: 'bar'});
+  t.ok(ssbKeys.verifyObj(keys, obj));
+  t.deepEqual(identity.toCurve(), ssbKeys.ssbSecretKeyToPrivateBoxSecret(keys));
+
+  let boxed = ssbKeys.box({hello: 'world'}, [identity]);
+  t.deepEqual(identity.unbox(boxed), {hello: 'world'});
+  t.deepEqual(identity.unboxKey(boxed), ssbKeys.unboxKey(boxed, keys));
+  t.end();
+});
+
+tape('existing functions accept the handle as keys', function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  let identity = ssbKeys.openIdentity(filename);
+
+  let sig = ssbKeys.sign(identity, 'hello');
+  t.ok(ssbKeys.verify(identity, sig, 'hello'));
+  t.ok(ssbKeys.verify(keys, sig, 'hello'));
+  let obj = ssbKeys.signObj(identity, {foo: 'bar'});
+  t.ok(ssbKeys.verifyObj(identity, obj));
+  t.end();
+});
+
+tape('openIdentity of a missing keyfile throws ERR_KEYFILE_NOT_FOUND', function (t) {
+  try {
+    ssbKeys.openIdentity(tmpPath('secret'));
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_KEYFILE_NOT_FOUND');
+  }
+  t.end();
+});
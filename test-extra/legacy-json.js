@@ -0,0 +1,54 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+let messages = require('./fixtures/legacy-messages.json').map((msg) => msg.value);
+
+function unsigned(msg) {
+  let copy = Object.assign({}, msg);
+  delete copy.signature;
+  return copy;
+}
+
+tape('signObj signs the same bytes as ssb-keys', function (t) {
+  let keys = ssbKeys.generate();
+  let hmacKey = crypto.randomBytes(32);
+  for (let msg of messages) {
+    let value = unsigned(msg);
+    t.deepEqual(ssbKeys.signObj(keys, value), jsKeys.signObj(keys, value), 'seq ' + msg.sequence);
+    t.deepEqual(ssbKeys.signObj(keys, hmacKey, value), jsKeys.signObj(keys, hmacKey, value));
+  }
+  t.end();
+});
+
+tape('verifyObj accepts what ssb-keys signed', function (t) {
+  let keys = ssbKeys.generate();
+  let signed = messages.map((msg) => jsKeys.signObj(keys, unsigned(msg)));
+  for (let msg of signed) t.true(ssbKeys.verifyObj(keys, msg), 'seq ' + msg.sequence);
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, null, signed), signed.map(() => true));
+  t.end();
+});
+
+tape('signObj serializes values like JSON.stringify', function (t) {
+  let keys = ssbKeys.generate();
+  let date = new Date(1449201682172);
+  let values = [
+    {skipped: undefined, fn() {}, arr: [undefined, function () {}, 1]},
+    {date, buf: Buffer.from('hello'), custom: {toJSON: (key) => 'key was ' + key}},
+    {inArray: [{toJSON: (key) => 'index ' + key}]},
+    {lone: 'a\ud800b', pair: '😀', escapes: '  \u001f'},
+    {nan: NaN, inf: -Infinity, negZero: -0, tie: 1792303907063.0312, big: 2 ** 70},
+  ];
+  for (let value of values) {
+    t.deepEqual(ssbKeys.signObj(keys, value), jsKeys.signObj(keys, value));
+  }
+  t.end();
+});
+
+tape('signObj throws like JSON.stringify on cycles', function (t) {
+  let keys = ssbKeys.generate();
+  let value = {a: {}};
+  value.a.b = value;
+  t.throws(() => ssbKeys.signObj(keys, value), TypeError);
+  t.end();
+});
@@ -0,0 +1,59 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('concurrent loadOrCreate calls converge on one keypair', function (t) {
+  let filename = tmpPath('secret');
+  let n = 8;
+  let results = [];
+  for (let i = 0; i < n; i++) {
+    ssbKeys.loadOrCreate(filename, function (err, keys) {
+      t.error(err);
+      results.push(keys.id);
+      if (results.length === n) {
+        t.equal(new Set(results).size, 1, 'all got the same id');
+        t.equal(ssbKeys.loadOrCreateSync(filename).id, results[0]);
+        t.end();
+      }
+    });
+  }
+});
+
+tape('createSync leaves no temp files behind', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  t.deepEqual(fs.readdirSync(path.dirname(filename)), ['secret']);
+  t.throws(() => ssbKeys.createSync(filename), /exists/, 'never overwrites');
+  t.end();
+});
+
+tape('loadOrCreateSync does not replace a corrupt keyfile', function (t) {
+  let filename = tmpPath('secret');
+  fs.mkdirSync(path.dirname(filename), {recursive: true});
+  fs.writeFileSync(filename, '# just a comment\n{"oops": ');
+  t.throws(() => ssbKeys.loadOrCreateSync(filename), /parse secret file as json/);
+  t.equal(fs.readFileSync(filename, 'utf8'), '# just a comment\n{"oops": ');
+  t.end();
+});
+
+tape('loadOrCreate with recover moves a corrupt keyfile aside', function (t) {
+  let filename = tmpPath('secret');
+  fs.mkdirSync(path.dirname(filename), {recursive: true});
+  fs.writeFileSync(filename, 'garbage');
+  ssbKeys.loadOrCreate(filename, {recover: true}, function (err, keys) {
+    t.error(err);
+    t.deepEqual(ssbKeys.loadSync(filename), keys);
+    let moved = fs
+      .readdirSync(path.dirname(filename))
+      .filter((name) => name.startsWith('secret.corrupt-'));
+    t.equal(moved.length, 1, 'kept the corrupt file');
+    t.equal(fs.readFileSync(path.join(path.dirname(filename), moved[0]), 'utf8'), 'garbage');
+    t.end();
+  });
+});
@@ -0,0 +1,43 @@
+let tape = require('tape');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+
+// What ssb-keys-mnemonic gives for this seed: each half is its own 12-word
+// mnemonic, here the BIP39 test vectors for 7f…7f and 80…80
+let seed = Buffer.concat([Buffer.alloc(16, 0x7f), Buffer.alloc(16, 0x80)]);
+let vector =
+  'legal winner thank year wave sausage worth useful legal winner thank yellow ' +
+  'letter advice cage absurd amount doctor acoustic avoid letter advice cage above';
+
+tape('toMnemonic and fromMnemonic match ssb-keys-mnemonic', function (t) {
+  let keys = jsKeys.generate('ed25519', seed);
+  t.equal(ssbKeys.toMnemonic(keys), vector);
+  t.deepEqual(ssbKeys.fromMnemonic(vector), keys);
+  t.end();
+});
+
+tape('toMnemonic and fromMnemonic roundtrip', function (t) {
+  let keys = ssbKeys.generate();
+  let words = ssbKeys.toMnemonic(keys);
+  t.equal(words.split(' ').length, 24, 'has 24 words');
+  t.deepEqual(ssbKeys.fromMnemonic(words), keys, 'same keys object');
+  t.deepEqual(ssbKeys.toMnemonic(keys.private), words, 'accepts private str');
+  t.end();
+});
+
+tape('fromMnemonic rejects bad words and bad checksums', function (t) {
+  let words = ssbKeys.toMnemonic(ssbKeys.generate()).split(' ');
+
+  t.throws(() => ssbKeys.fromMnemonic(words.slice(0, 12).join(' ')), /24 words/);
+
+  let unknown = words.slice();
+  unknown[3] = 'notaword';
+  t.throws(() => ssbKeys.fromMnemonic(unknown.join(' ')), /#4 "notaword"/);
+
+  // The checksum of 16 zero bytes is 3, for 'about'
+  let zeros = Array(12).fill('abandon');
+  t.throws(() => ssbKeys.fromMnemonic(zeros.concat(words.slice(12)).join(' ')), /checksum in its first half/);
+  t.throws(() => ssbKeys.fromMnemonic(words.slice(0, 12).concat(zeros).join(' ')), /checksum in its second half/);
+  t.throws(() => ssbKeys.fromMnemonic(words.slice(0, 23).concat('notaword').join(' ')), /#24 "notaword"/);
+  t.end();
+});
@@ -0,0 +1,51 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+// A feed of {key, value} records, signed with node's own ed25519 and hashed
+// with its sha256, so neither this module nor ssb-keys produced it. Its content
+// has unicode, escapes, floats, exponents, and large and nested objects
+let corpus = require('./fixtures/legacy-messages.json');
+let messages = corpus.map((msg) => msg.value);
+
+// How ssb-validate computes message ids
+function jsId(value) {
+  return '%' + jsKeys.hash(JSON.stringify(value, null, 2));
+}
+
+tape('msgId matches ssb-validate', function (t) {
+  for (let msg of messages) t.equal(ssbKeys.msgId(msg), jsId(msg), 'seq ' + msg.sequence);
+  t.deepEqual(ssbKeys.msgIdBatch(messages), messages.map(jsId));
+  t.deepEqual(ssbKeys.msgIdBatch([]), []);
+  t.end();
+});
+
+tape('the message corpus verifies and hashes to its recorded keys', function (t) {
+  for (let {key, value} of corpus) {
+    t.true(ssbKeys.verifyObj(value.author, value), 'signature of seq ' + value.sequence);
+    t.equal(ssbKeys.msgId(value), key, 'key of seq ' + value.sequence);
+  }
+  t.deepEqual(ssbKeys.msgIdBatch(messages), corpus.map((msg) => msg.key));
+  t.equal(ssbKeys.validateBatch(null, null, messages).id, corpus[corpus.length - 1].key);
+
+  t.ok(messages.some((m) => /[^\x00-\x7f]/.test(JSON.stringify(m))), 'has non-ASCII messages');
+  t.ok(messages.some((m) => !Number.isInteger(m.timestamp)), 'has float timestamps');
+  t.ok(messages.some((m) => /\de[+-]\d/.test(JSON.stringify(m))), 'has exponents');
+  t.ok(messages.some((m) => /\\u0000/.test(JSON.stringify(m))), 'has escaped control characters');
+  t.end();
+});
+
+tape('msgId hashes non-ASCII content as latin1, not UTF-8', function (t) {
+  let msg = messages.find((m) => /[^\x00-\x7f]/.test(JSON.stringify(m)));
+  let utf8 = crypto.createHash('sha256').update(JSON.stringify(msg, null, 2), 'utf8').digest('base64');
+  t.notEqual(ssbKeys.msgId(msg), '%' + utf8 + '.sha256');
+  t.equal(ssbKeys.msgId(msg), jsId(msg));
+  t.end();
+});
+
+tape('msgId validates its argument', function (t) {
+  t.throws(() => ssbKeys.msgId('not a message'), /message value object/);
+  t.throws(() => ssbKeys.msgIdBatch([messages[0], 42]), /message value objects/);
+  t.throws(() => ssbKeys.msgIdBatch(messages[0]), /array of message values/);
+  t.end();
+});
@@ -0,0 +1,73 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+let skip = process.platform === 'win32';
+
+tape('createSync writes keyfiles with mode 0400', {skip}, function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  t.equal(fs.statSync(filename).mode & 0o777, 0o400);
+  t.deepEqual(ssbKeys.auditKeyfile(filename), []);
+  t.end();
+});
+
+tape('auditKeyfile reports a world-readable keyfile', {skip}, function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  fs.chmodSync(filename, 0o644);
+  let problems = ssbKeys.auditKeyfile(filename);
+  t.deepEqual(problems.map((p) => p.kind), ['mode']);
+  t.ok(problems[0].message.includes('0644'));
+  t.end();
+});
+
+tape('auditKeyfile reports symlinks and writable parent dirs', {skip}, function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  let link = filename + '-link';
+  fs.symlinkSync(filename, link);
+  fs.chmodSync(path.dirname(filename), 0o777);
+  let kinds = ssbKeys.auditKeyfile(link).map((p) => p.kind);
+  t.deepEqual(kinds, ['symlink', 'parent']);
+  t.end();
+});
+
+tape('loadSync applies the permissions policy', {skip}, function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  fs.chmodSync(filename, 0o644);
+
+  t.deepEqual(ssbKeys.loadSync(filename), keys, 'ignored by default');
+  t.throws(
+    () => ssbKeys.loadSync(filename, {permissions: 'refuse'}),
+    /refusing to load insecure keyfile/,
+  );
+
+  let warnings = [];
+  let loaded = ssbKeys.loadSync(filename, {
+    permissions: 'warn',
+    onWarning: (problems) => warnings.push(...problems),
+  });
+  t.deepEqual(loaded, keys);
+  t.deepEqual(warnings.map((p) => p.kind), ['mode']);
+  t.end();
+});
+
+tape('load refuses through the callback', {skip}, function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  fs.chmodSync(filename, 0o666);
+  ssbKeys.load(filename, {permissions: 'refuse'}, function (err, keys) {
+    t.ok(err);
+    t.match(err.message, /has mode 0666/);
+    t.notOk(keys);
+    t.end();
+  });
+});
@@ -0,0 +1,60 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpDir() {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random());
+}
+
+tape('addIdentity creates loadable profiles, the first one being default', function (t) {
+  let dir = tmpDir();
+  t.deepEqual(ssbKeys.listIdentities(dir), [], 'empty when the dir is missing');
+  t.equal(ssbKeys.getDefault(dir), null);
+
+  let work = ssbKeys.addIdentity(dir, 'work');
+  let home = ssbKeys.addIdentity(dir, 'home');
+  t.equal(work.label, 'work');
+  t.true(work.isDefault);
+  t.false(home.isDefault);
+
+  t.equal(ssbKeys.loadSync(work.path).id, work.id, 'works with loadSync');
+  t.deepEqual(
+    ssbKeys.listIdentities(dir).map((p) => p.label).sort(),
+    ['home', 'work'],
+  );
+  t.equal(ssbKeys.getDefault(dir).id, work.id);
+  t.throws(() => ssbKeys.addIdentity(dir, 'work'), /already exists/);
+  t.end();
+});
+
+tape('setDefault and removeIdentity', function (t) {
+  let dir = tmpDir();
+  let a = ssbKeys.addIdentity(dir, 'a');
+  let b = ssbKeys.addIdentity(dir, 'b');
+
+  t.true(ssbKeys.setDefault(dir, b.id).isDefault);
+  t.equal(ssbKeys.getDefault(dir).id, b.id);
+  t.throws(() => ssbKeys.setDefault(dir, '@nope.ed25519'), /no identity/);
+
+  t.true(ssbKeys.removeIdentity(dir, b.id));
+  t.false(ssbKeys.removeIdentity(dir, b.id), 'already removed');
+  t.equal(ssbKeys.getDefault(dir), null, 'default was removed');
+  t.deepEqual(ssbKeys.listIdentities(dir).map((p) => p.id), [a.id]);
+
+  let removed = fs.readdirSync(path.join(dir, '.removed'));
+  t.equal(removed.length, 1, 'keyfile was kept aside');
+  t.equal(ssbKeys.loadSync(path.join(dir, '.removed', removed[0])).id, b.id);
+  t.end();
+});
+
+tape('load works with a profile path', function (t) {
+  let dir = tmpDir();
+  let profile = ssbKeys.addIdentity(dir, 'main');
+  ssbKeys.load(profile.path, function (err, keys) {
+    t.error(err);
+    t.equal(keys.id, profile.id);
+    t.end();
+  });
+});
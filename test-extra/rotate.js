@@ -0,0 +1,64 @@
+let tape = require('tape');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('rotateIdentity archives the old keyfile and links both ids', function (t) {
+  let filename = tmpPath('secret');
+  let oldKeys = ssbKeys.createSync(filename);
+  let {keys, statement, archive} = ssbKeys.rotateIdentity(filename, {reason: 'lost laptop'});
+
+  t.notEqual(keys.id, oldKeys.id);
+  t.deepEqual(ssbKeys.loadSync(filename), keys, 'new keyfile in place');
+  t.deepEqual(ssbKeys.loadSync(archive), oldKeys, 'old keyfile archived');
+  t.ok(path.basename(archive).startsWith('secret.rotated-'));
+
+  t.equal(statement.type, 'ssb-identity-rotation');
+  t.equal(statement.old, oldKeys.id);
+  t.equal(statement.new, keys.id);
+  t.equal(statement.reason, 'lost laptop');
+  t.true(ssbKeys.verifyRotation(statement));
+  t.true(ssbKeys.verifyRotation(JSON.stringify(statement)), 'accepts a string');
+  t.end();
+});
+
+tape('verifyRotation rejects tampered statements', function (t) {
+  let filename = tmpPath('secret');
+  ssbKeys.createSync(filename);
+  let {statement} = ssbKeys.rotateIdentity(filename);
+  let other = ssbKeys.generate();
+
+  t.false(ssbKeys.verifyRotation({...statement, new: other.id}));
+  t.false(ssbKeys.verifyRotation({...statement, timestamp: statement.timestamp + 1}));
+  t.false(
+    ssbKeys.verifyRotation({...statement, countersignature: statement.signature}),
+    'needs the old key countersignature',
+  );
+  t.throws(() => ssbKeys.verifyRotation({...statement, old: 'nope'}), /invalid `old`/);
+  t.throws(() => ssbKeys.verifyRotation({type: 'ssb-identity-rotation'}), /malformed/);
+  t.end();
+});
+
+tape('rotateIdentity fails without an existing keyfile', function (t) {
+  let filename = tmpPath('secret');
+  t.throws(() => ssbKeys.rotateIdentity(filename), /Failed to read/);
+  t.false(fs.existsSync(filename));
+  t.end();
+});
+
+tape('rotateIdentity keeps the old keyfile linked, not moved, and cleans up', function (t) {
+  let filename = tmpPath('secret');
+  let oldKeys = ssbKeys.createSync(filename);
+  let oldContents = fs.readFileSync(filename, 'utf8');
+  let {archive} = ssbKeys.rotateIdentity(filename);
+  t.equal(fs.readFileSync(archive, 'utf8'), oldContents, 'archive is the old file byte for byte');
+  t.deepEqual(ssbKeys.loadSync(archive), oldKeys);
+  let names = fs.readdirSync(path.dirname(filename)).filter((name) => !name.endsWith('.lock'));
+  t.deepEqual(names.sort(), ['secret', path.basename(archive)].sort(), 'no temp files left');
+  t.end();
+});
@@ -0,0 +1,46 @@
+let tape = require('tape');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+tape('secureMemoryStatus reports whether pages can be locked', function (t) {
+  let status = ssbKeys.secureMemoryStatus();
+  t.equal(typeof status.enabled, 'boolean');
+  t.equal(typeof status.locked, 'boolean');
+  t.equal(status.supported, process.platform !== 'win32');
+  if (status.supported) t.equal(typeof status.memlockLimit, 'number');
+  t.end();
+});
+
+tape('secureMemoryStatus reports the same probe result each time', function (t) {
+  let first = ssbKeys.secureMemoryStatus();
+  for (let i = 0; i < 100; i++) {
+    t.deepEqual(ssbKeys.secureMemoryStatus(), first);
+  }
+  t.end();
+});
+
+tape('identities opened with secure memory enabled are locked', function (t) {
+  let status = ssbKeys.enableSecureMemory();
+  t.ok(status.enabled);
+
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  let identity = ssbKeys.openIdentity(filename);
+  t.equal(identity.sign('hello'), ssbKeys.sign(keys, 'hello'));
+
+  status = ssbKeys.secureMemoryStatus();
+  if (!status.supported) {
+    t.equal(status.lockedBytes, 0);
+  } else if (status.locked) {
+    t.ok(status.lockedBytes > 0, 'some bytes are locked');
+    t.equal(status.unlockedSecrets, 0);
+  } else {
+    t.ok(status.error, 'says why locking failed: ' + status.error);
+  }
+  t.end();
+});
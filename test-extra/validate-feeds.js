@@ -0,0 +1,128 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let ssbKeys = require('../');
+
+function feed(keys, hmacKey, n) {
+  let contents = [];
+  for (let i = 0; i < n; i++) contents.push({type: 'post', text: 'hello ' + i});
+  return ssbKeys.createMessageBatch(keys, hmacKey, null, contents).map((msg) => msg.value);
+}
+
+tape('validateFeeds validates each feed', async function (t) {
+  let hmacKey = crypto.randomBytes(32);
+  let feeds = {};
+  for (let i = 0; i < 8; i++) {
+    let keys = ssbKeys.generate();
+    feeds[keys.id] = feed(keys, hmacKey, 20 + i);
+  }
+  let results = await ssbKeys.validateFeeds(feeds, hmacKey);
+  t.deepEqual(Object.keys(results), Object.keys(feeds));
+  for (let [id, msgs] of Object.entries(feeds)) {
+    t.deepEqual(results[id], {
+      state: ssbKeys.validateBatch(null, hmacKey, msgs),
+      validated: msgs.length,
+      error: null,
+    });
+  }
+  t.deepEqual(await ssbKeys.validateFeeds({}), {});
+  t.end();
+});
+
+tape('validateFeeds reports the first failure of each feed', async function (t) {
+  let good = ssbKeys.generate();
+  let bad = ssbKeys.generate();
+  let stranger = ssbKeys.generate();
+  let badMsgs = feed(bad, null, 5);
+  badMsgs[2] = Object.assign({}, badMsgs[2], {content: {type: 'tampered'}});
+  let feeds = {
+    [good.id]: feed(good, null, 5),
+    [bad.id]: badMsgs,
+    [stranger.id]: feed(good, null, 1),
+  };
+
+  let results = await ssbKeys.validateFeeds(feeds, null);
+  t.equal(results[good.id].validated, 5);
+  t.equal(results[good.id].error, null);
+
+  t.equal(results[bad.id].validated, 2);
+  t.equal(results[bad.id].error.code, 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+  t.deepEqual(results[bad.id].state, ssbKeys.validateBatch(null, null, badMsgs.slice(0, 2)));
+
+  t.equal(results[stranger.id].validated, 0);
+  t.equal(results[stranger.id].state, null);
+  t.equal(results[stranger.id].error.code, 'ERR_SSB_KEYS_INVALID_MESSAGE');
+  t.equal(results[stranger.id].error.field, 'author');
+  t.end();
+});
+
+tape('validateFeeds continues from the given states', async function (t) {
+  let keys = ssbKeys.generate();
+  let msgs = feed(keys, null, 6);
+  let state = ssbKeys.validateBatch(null, null, msgs.slice(0, 3));
+  let results = await ssbKeys.validateFeeds({[keys.id]: msgs.slice(3)}, null, {states: {[keys.id]: state}});
+  t.equal(results[keys.id].validated, 3);
+  t.deepEqual(results[keys.id].state, ssbKeys.validateBatch(null, null, msgs));
+
+  results = await ssbKeys.validateFeeds({[keys.id]: msgs.slice(3)});
+  t.equal(results[keys.id].error.code, 'ERR_SSB_KEYS_OUT_OF_ORDER');
+  t.end();
+});
+
+tape('validateFeeds reports the progress of each feed', async function (t) {
+  let big = ssbKeys.generate();
+  let small = ssbKeys.generate();
+  let bad = ssbKeys.generate();
+  let badMsgs = feed(bad, null, 2500);
+  badMsgs[1500] = Object.assign({}, badMsgs[1500], {content: {type: 'tampered'}});
+  let feeds = {[big.id]: feed(big, null, 2500), [small.id]: feed(small, null, 10), [bad.id]: badMsgs};
+
+  let progress = {};
+  let onProgress = (id, validated) => (progress[id] = progress[id] || []).push(validated);
+  let results = await ssbKeys.validateFeeds(feeds, null, {onProgress});
+  t.deepEqual(progress, {[big.id]: [1000, 2000, 2500], [small.id]: [10], [bad.id]: [1000, 1500]});
+  t.equal(results[big.id].validated, 2500);
+  t.deepEqual(results[big.id].state, ssbKeys.validateBatch(null, null, feeds[big.id]));
+  t.equal(results[bad.id].validated, 1500);
+  t.equal(results[bad.id].error.code, 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+
+  try {
+    await ssbKeys.validateFeeds(feeds, null, {onProgress: () => { throw new Error('stop'); }});
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.message, 'stop');
+  }
+  t.end();
+});
+
+tape('validateFeeds rejects bad arguments', async function (t) {
+  try {
+    await ssbKeys.validateFeeds({notAFeed: []});
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_VALUE');
+  }
+  try {
+    await ssbKeys.validateFeeds({[ssbKeys.generate().id]: 'not an array'});
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+  }
+  let keys = ssbKeys.generate();
+  let msgs = feed(keys, null, 1500);
+  msgs[1200] = 'not a message';
+  try {
+    await ssbKeys.validateFeeds({[keys.id]: msgs});
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+    t.equal(err.argIndex, 0);
+  }
+  try {
+    await ssbKeys.validateFeeds({}, null, {onProgress: 'not a function'});
+    t.fail('should have rejected');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+    t.equal(err.argIndex, 2);
+  }
+  t.end();
+});
@@ -0,0 +1,178 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let jsKeys = require('ssb-keys');
+let ssbKeys = require('../');
+
+// How ssb-validate computes message ids
+function jsId(value) {
+  return '%' + jsKeys.hash(JSON.stringify(value, null, 2));
+}
+
+// Signs the message after `prev` with ssb-keys, with `fields` overriding any
+function next(keys, hmacKey, prev, content, fields) {
+  let value = {
+    previous: prev ? jsId(prev) : null,
+    author: keys.id,
+    sequence: prev ? prev.sequence + 1 : 1,
+    timestamp: prev ? prev.timestamp + 1 : 1600000000000,
+    hash: 'sha256',
+    content,
+  };
+  return jsKeys.signObj(keys, hmacKey, Object.assign(value, fields));
+}
+
+function feed(keys, hmacKey, n) {
+  let msgs = [];
+  for (let i = 0; i < n; i++) {
+    msgs.push(next(keys, hmacKey, msgs[i - 1], {type: 'post', text: 'hello ' + i}));
+  }
+  return msgs;
+}
+
+function code(fn) {
+  try {
+    fn();
+  } catch (err) {
+    return err.code;
+  }
+}
+
+tape('validate returns the new state of the feed', function (t) {
+  let keys = ssbKeys.generate();
+  let msgs = feed(keys, null, 5);
+  let state = null;
+  for (let msg of msgs) {
+    state = ssbKeys.validate(state, null, msg);
+    t.deepEqual(state, {author: keys.id, id: jsId(msg), sequence: msg.sequence, timestamp: msg.timestamp});
+  }
+  t.deepEqual(ssbKeys.validateBatch(null, null, msgs), state);
+  t.deepEqual(ssbKeys.validateBatch(ssbKeys.validate(null, null, msgs[0]), null, msgs.slice(1)), state);
+  t.equal(ssbKeys.validateBatch(null, null, []), null);
+  t.end();
+});
+
+tape('validate checks the signature with the hmac key and the author', function (t) {
+  let keys = ssbKeys.generate();
+  let hmacKey = crypto.randomBytes(32);
+  let msg = next(keys, hmacKey, null, {type: 'post'});
+  t.equal(ssbKeys.validate(null, hmacKey, msg).id, jsId(msg));
+  t.equal(ssbKeys.validate(null, hmacKey.toString('base64'), msg).id, jsId(msg));
+  t.equal(code(() => ssbKeys.validate(null, null, msg)), 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+
+  let other = ssbKeys.generate();
+  let forged = next(other, null, null, {type: 'post'}, {author: keys.id});
+  t.equal(code(() => ssbKeys.validate(null, null, forged)), 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+  let tampered = Object.assign({}, next(keys, null, null, {type: 'post'}), {timestamp: 1});
+  t.equal(code(() => ssbKeys.validate(null, null, tampered)), 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+  t.end();
+});
+
+tape('validate checks the shape of messages', function (t) {
+  let keys = ssbKeys.generate();
+  let cases = [
+    [{type: 'post'}, {hash: 'sha1'}, 'hash'],
+    [{type: 'post'}, {timestamp: '1600000000000'}, 'timestamp'],
+    [{type: 'post'}, {sequence: 1.5}, 'sequence'],
+    [{type: 'post'}, {previous: jsId({})}, 'previous'],
+    [{type: 'post'}, {author: 'not a feed'}, 'author'],
+    [{type: 'ab'}, {}, 'content'],
+    [{type: 'x'.repeat(53)}, {}, 'content'],
+    [{text: 'no type'}, {}, 'content'],
+    ['not boxed', {}, 'content'],
+    [42, {}, 'content'],
+    [{type: 'post', text: 'x'.repeat(8192)}, {}, undefined],
+  ];
+  for (let [content, fields, field] of cases) {
+    let msg = next(keys, null, null, content, fields);
+    try {
+      ssbKeys.validate(null, null, msg);
+      t.fail('should have thrown for ' + field);
+    } catch (err) {
+      t.equal(err.code, 'ERR_SSB_KEYS_INVALID_MESSAGE', err.message);
+      t.equal(err.field, field);
+    }
+  }
+
+  let boxed = ssbKeys.box({type: 'post'}, [keys]);
+  t.equal(ssbKeys.validate(null, null, next(keys, null, null, boxed)).sequence, 1);
+  t.equal(ssbKeys.validate(null, null, next(keys, null, null, 'YWJj.box2')).sequence, 1);
+  t.end();
+});
+
+tape('validate checks the order of the keys like ssb-validate', function (t) {
+  let keys = ssbKeys.generate();
+  let value = next(keys, null, null, {type: 'post'});
+  let sign = (order) => {
+    let unsigned = {};
+    for (let key of order) unsigned[key] = value[key];
+    return jsKeys.signObj(keys, null, unsigned);
+  };
+
+  let swapped = sign(['previous', 'sequence', 'author', 'timestamp', 'hash', 'content']);
+  t.equal(ssbKeys.validate(null, null, swapped).id, jsId(swapped));
+
+  let cases = [
+    sign(['previous', 'author', 'sequence', 'hash', 'timestamp', 'content']),
+    sign(['author', 'previous', 'sequence', 'timestamp', 'hash', 'content']),
+    next(keys, null, null, {type: 'post'}, {extra: true}),
+    Object.assign({}, value, {extra: true}),
+  ];
+  for (let msg of cases) {
+    try {
+      ssbKeys.validate(null, null, msg);
+      t.fail('should have thrown for ' + Object.keys(msg));
+    } catch (err) {
+      t.equal(err.code, 'ERR_SSB_KEYS_INVALID_MESSAGE', err.message);
+      t.equal(err.field, undefined);
+    }
+  }
+  t.end();
+});
+
+tape('validate detects gaps, duplicates and forks', function (t) {
+  let keys = ssbKeys.generate();
+  let msgs = feed(keys, null, 3);
+  let state = ssbKeys.validateBatch(null, null, msgs.slice(0, 2));
+
+  t.equal(code(() => ssbKeys.validate(null, null, msgs[1])), 'ERR_SSB_KEYS_OUT_OF_ORDER');
+  t.equal(code(() => ssbKeys.validate(state, null, msgs[0])), 'ERR_SSB_KEYS_OUT_OF_ORDER');
+  t.equal(code(() => ssbKeys.validate(state, null, msgs[1])), 'ERR_SSB_KEYS_OUT_OF_ORDER');
+  let after = next(keys, null, msgs[2], {type: 'post'});
+  t.equal(code(() => ssbKeys.validate(state, null, after)), 'ERR_SSB_KEYS_OUT_OF_ORDER');
+
+  let sibling = next(keys, null, msgs[0], {type: 'post', text: 'other'});
+  t.equal(code(() => ssbKeys.validate(state, null, sibling)), 'ERR_SSB_KEYS_FORK');
+  let child = next(keys, null, sibling, {type: 'post'});
+  t.equal(code(() => ssbKeys.validate(state, null, child)), 'ERR_SSB_KEYS_FORK');
+
+  let other = ssbKeys.generate();
+  let stranger = next(other, null, msgs[1], {type: 'post'}, {author: other.id});
+  t.equal(code(() => ssbKeys.validate(state, null, stranger)), 'ERR_SSB_KEYS_INVALID_MESSAGE');
+  t.end();
+});
+
+tape('validateBatch reports where the run stopped', function (t) {
+  let keys = ssbKeys.generate();
+  let msgs = feed(keys, null, 4);
+  msgs[2] = Object.assign({}, msgs[2], {content: {type: 'tampered'}});
+  try {
+    ssbKeys.validateBatch(null, null, msgs);
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_SSB_KEYS_INVALID_SIGNATURE');
+    t.equal(err.index, 2);
+    t.deepEqual(err.state, ssbKeys.validateBatch(null, null, msgs.slice(0, 2)));
+  }
+  t.end();
+});
+
+tape('validate checks its arguments', function (t) {
+  let keys = ssbKeys.generate();
+  let msg = next(keys, null, null, {type: 'post'});
+  t.equal(code(() => ssbKeys.validate(null, null)), 'ERR_MISSING_ARGS');
+  t.equal(code(() => ssbKeys.validate(null, null, 'msg')), 'ERR_INVALID_ARG_TYPE');
+  t.equal(code(() => ssbKeys.validate(42, null, msg)), 'ERR_INVALID_ARG_TYPE');
+  t.equal(code(() => ssbKeys.validate({sequence: 1}, null, msg)), 'ERR_INVALID_ARG_VALUE');
+  t.equal(code(() => ssbKeys.validateBatch(null, null, [msg, 42])), 'ERR_INVALID_ARG_TYPE');
+  t.end();
+});
@@ -0,0 +1,87 @@
+let tape = require('tape');
+let ssbKeys = require('../');
+
+tape('generateVanity finds a matching prefix', function (t) {
+  ssbKeys.generateVanity('@A', {threads: 2}, function (err, res) {
+    t.error(err);
+    t.ok(res.keys.id.startsWith('@A'), 'id has the prefix');
+    t.ok(res.attempts >= 1, 'reports attempts');
+    t.end();
+  });
+});
+
+tape('generateVanity accepts a RegExp', function (t) {
+  ssbKeys.generateVanity(/^@ab/i, function (err, res) {
+    t.error(err);
+    t.ok(/^@ab/i.test(res.keys.id), 'id matches the regex');
+    ssbKeys.generateVanity(/^@.{43}=\.ed25519$/, {maxAttempts: 1}, function (err, res) {
+      t.error(err, 'the regex sees the whole id');
+      t.ok(res.keys.id.endsWith('=.ed25519'));
+      t.end();
+    });
+  });
+});
+
+tape('generateVanity respects the attempt budget', function (t) {
+  ssbKeys.generateVanity('zzzzzzzz', {maxAttempts: 100}, function (err) {
+    t.match(err.message, /gave up after 100 attempts/);
+    t.end();
+  });
+});
+
+tape('generateVanity can be cancelled', function (t) {
+  let id = ssbKeys.generateVanity('zzzzzzzz', {threads: 1}, function (err) {
+    t.match(err.message, /cancelled/);
+    t.notOk(ssbKeys.cancelVanity(id), 'search is gone after it ends');
+    t.end();
+  });
+  t.ok(ssbKeys.cancelVanity(id), 'search was running');
+});
+
+tape('generateVanity bounds opts.threads', function (t) {
+  for (let threads of [0, 1.5, 1025, Infinity, 1e9]) {
+    try {
+      ssbKeys.generateVanity('@A', {threads}, function () {
+        t.fail('should not have started');
+      });
+      t.fail('should have thrown');
+    } catch (err) {
+      t.ok(err instanceof RangeError);
+      t.equal(err.code, 'ERR_INVALID_ARG_VALUE', 'threads: ' + threads);
+      t.equal(err.argIndex, 1);
+    }
+  }
+  t.end();
+});
+
+tape('generateVanity needs a finite budget', function (t) {
+  for (let opts of [{timeout: Infinity}, {maxAttempts: Infinity}, {timeout: -1}, {maxAttempts: NaN}]) {
+    try {
+      ssbKeys.generateVanity('@A', opts, function () {
+        t.fail('should not have started');
+      });
+      t.fail('should have thrown');
+    } catch (err) {
+      t.ok(err instanceof RangeError);
+      t.equal(err.code, 'ERR_INVALID_ARG_VALUE', JSON.stringify(opts));
+      t.equal(err.argIndex, 1);
+    }
+  }
+  t.end();
+});
+
+tape('generateVanity only takes the i flag', function (t) {
+  for (let regex of [/a/g, /a/y, /a/u, /a/s, /a/m, /a/gi]) {
+    try {
+      ssbKeys.generateVanity(regex, {maxAttempts: 1}, function () {
+        t.fail('should not have started');
+      });
+      t.fail('should have thrown');
+    } catch (err) {
+      t.ok(err instanceof RangeError);
+      t.equal(err.code, 'ERR_INVALID_ARG_VALUE', String(regex));
+      t.equal(err.argIndex, 0);
+    }
+  }
+  t.end();
+});
@@ -0,0 +1,110 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let ssbKeys = require('../');
+
+tape('verifyObjBatch agrees with verifyObj', function (t) {
+  let keys = ssbKeys.generate();
+  let other = ssbKeys.generate();
+  let objs = [];
+  for (let i = 0; i < 20; i++) objs.push(ssbKeys.signObj(keys, {seq: i, text: 'hello ' + i}));
+
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, null, objs), objs.map(() => true));
+  t.deepEqual(ssbKeys.verifyObjBatch(keys.id, null, objs), objs.map(() => true));
+
+  objs[3] = Object.assign({}, objs[3], {text: 'tampered'});
+  objs[7] = ssbKeys.signObj(other, {seq: 7});
+  objs[9] = {seq: 9};
+  let expected = objs.map((obj) => !!obj.signature && ssbKeys.verifyObj(keys, obj));
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, null, objs), expected);
+  t.deepEqual(expected.filter((ok) => !ok).length, 3);
+  t.end();
+});
+
+tape('verifyObjBatch takes one key per object, and an hmac key', function (t) {
+  let hmacKey = crypto.randomBytes(32);
+  let keys = [ssbKeys.generate(), ssbKeys.generate()];
+  let objs = keys.map((k) => ssbKeys.signObj(k, hmacKey, {foo: 'bar'}));
+
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, hmacKey, objs), [true, true]);
+  t.deepEqual(ssbKeys.verifyObjBatch(keys.map((k) => k.id), hmacKey.toString('base64'), objs), [true, true]);
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, null, objs), [false, false]);
+  t.deepEqual(ssbKeys.verifyObjBatch([keys[1], keys[0]], hmacKey, objs), [false, false]);
+  t.end();
+});
+
+tape('verifyObjBatch validates its arguments', function (t) {
+  let keys = ssbKeys.generate();
+  t.deepEqual(ssbKeys.verifyObjBatch(keys, null, []), []);
+  try {
+    ssbKeys.verifyObjBatch([keys], null, [{}, {}]);
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_VALUE');
+    t.equal(err.argIndex, 0);
+  }
+  try {
+    ssbKeys.verifyObjBatch(keys, null, {});
+    t.fail('should have thrown');
+  } catch (err) {
+    t.equal(err.code, 'ERR_INVALID_ARG_TYPE');
+    t.equal(err.argIndex, 2);
+  }
+  t.end();
+});
+
+// Little-endian bytes and BigInts, for the scalar and field arithmetic below
+function toBig(buf) {
+  return BigInt('0x' + Buffer.from(buf).reverse().toString('hex'));
+}
+function fromBig(n) {
+  return Buffer.from(n.toString(16).padStart(64, '0'), 'hex').reverse();
+}
+const L = 2n ** 252n + 27742317777372353535851937790883648493n;
+const P = 2n ** 255n - 19n;
+
+// The ed25519 secret scalar of a seed
+function scalar(seed) {
+  let h = crypto.createHash('sha512').update(seed).digest().subarray(0, 32);
+  h[0] &= 248;
+  h[31] &= 127;
+  h[31] |= 64;
+  return toBig(h);
+}
+
+tape('verifyObjBatch ignores small order components when the batch holds', function (t) {
+  // A' = A + T, where T = (0, -1) has order 2, so A' = (-x, -y)
+  let seed = crypto.randomBytes(32);
+  let keys = ssbKeys.generate('ed25519', seed);
+  let a = Buffer.from(keys.public.replace('.ed25519', ''), 'base64');
+  let sign = a[31] & 128;
+  let y = toBig(Buffer.concat([a.subarray(0, 31), Buffer.from([a[31] & 127])]));
+  let evil = fromBig(P - y);
+  evil[31] |= sign ^ 128;
+  let evilId = '@' + evil.toString('base64') + '.ed25519';
+
+  // A signature with s = r + k * a, so sB - kA' = R - kT, which is R + T when
+  // k is odd. verifyObj rejects it, while the cofactored batch check always
+  // accepts it
+  let nonce = crypto.randomBytes(32);
+  let r = scalar(nonce);
+  let R = Buffer.from(ssbKeys.generate('ed25519', nonce).public.replace('.ed25519', ''), 'base64');
+  let obj;
+  let k;
+  for (let i = 0; k === undefined || k % 2n === 0n; i++) {
+    obj = {type: 'test', i};
+    let msg = Buffer.from(JSON.stringify(obj, null, 2));
+    k = toBig(crypto.createHash('sha512').update(R).update(evil).update(msg).digest()) % L;
+  }
+  let s = (r + k * scalar(seed)) % L;
+  obj.signature = Buffer.concat([R, fromBig(s)]).toString('base64') + '.sig.ed25519';
+
+  t.false(ssbKeys.verifyObj(evilId, obj));
+  t.deepEqual(ssbKeys.verifyObjBatch(evilId, null, [obj]), [true]);
+  let valid = ssbKeys.signObj(keys, {type: 'test'});
+  t.deepEqual(ssbKeys.verifyObjBatch([evilId, keys.id], null, [obj, valid]), [true, true]);
+
+  // When the batch fails each signature is checked like verifyObj does
+  let tampered = Object.assign({}, valid, {type: 'tampered'});
+  t.deepEqual(ssbKeys.verifyObjBatch([evilId, keys.id], null, [obj, tampered]), [false, false]);
+  t.end();
+});
@@ -0,0 +1,86 @@
+let tape = require('tape');
+let crypto = require('crypto');
+let fs = require('fs');
+let os = require('os');
+let path = require('path');
+let ssbKeys = require('../');
+
+function tmpPath(name) {
+  return path.join(os.tmpdir(), 'ssb-keys-neon-' + Date.now() + '-' + Math.random(), name);
+}
+
+// The secret is kept as two random shares, a and a^secret, so that the test
+// itself never holds a copy of it that the scan could find
+function split(secret) {
+  let a = crypto.randomBytes(secret.length);
+  let b = Buffer.alloc(secret.length);
+  for (let i = 0; i < secret.length; i++) b[i] = a[i] ^ secret[i];
+  secret.fill(0);
+  return [a, b];
+}
+
+// Whether the secret is anywhere in the writable memory of this process,
+// except the main thread's stack which still holds leftovers of sync calls
+function inMemory([a, b]) {
+  let first = a[0] ^ b[0];
+  let regions = fs
+    .readFileSync('/proc/self/maps', 'utf8')
+    .split('\n')
+    .filter((line) => / rw.p /.test(line) && !line.endsWith('[stack]'))
+    .map((line) => line.split(' ')[0].split('-').map((n) => parseInt(n, 16)));
+
+  let fd = fs.openSync('/proc/self/mem', 'r');
+  let chunk = Buffer.alloc(1 << 20);
+  try {
+    for (let [start, end] of regions) {
+      for (let pos = start; pos < end; pos += chunk.length - a.length) {
+        let n;
+        try {
+          n = fs.readSync(fd, chunk, 0, Math.min(chunk.length, end - pos), pos);
+        } catch (err) {
+          break;
+        }
+        for (let i = chunk.indexOf(first); i !== -1 && i <= n - a.length; i = chunk.indexOf(first, i + 1)) {
+          let k = 1;
+          while (k < a.length && (chunk[i + k] ^ a[k]) === b[k]) k++;
+          if (k === a.length) return true;
+        }
+      }
+    }
+    return false;
+  } finally {
+    fs.closeSync(fd);
+    chunk.fill(0);
+  }
+}
+
+let linux = process.platform === 'linux';
+
+tape('the secret key is not left in memory after use', {skip: !linux}, function (t) {
+  let filename = tmpPath('secret');
+  let keys = ssbKeys.createSync(filename);
+  let seed = split(Buffer.from(keys.private.replace('.ed25519', ''), 'base64').slice(0, 32));
+
+  ssbKeys.loadSync(filename);
+  let sig = ssbKeys.sign(keys, 'hello');
+  ssbKeys.verify(keys, sig, 'hello');
+  ssbKeys.signObj(keys, {foo: 'bar'});
+  ssbKeys.unbox(ssbKeys.box({hello: 'world'}, [keys]), keys);
+  ssbKeys.fromMnemonic(ssbKeys.toMnemonic(keys));
+  ssbKeys.fromPEM(ssbKeys.toPEM(keys).private);
+  ssbKeys.fromJWK(ssbKeys.toJWK(keys));
+  ssbKeys.fromOpenSSH(ssbKeys.toOpenSSH(keys).private);
+
+  t.notOk(inMemory(seed), 'no copy of the private key seed');
+  t.end();
+});
+
+tape('the secret box key is not left in memory after use', {skip: !linux}, function (t) {
+  let key = crypto.randomBytes(32);
+  let boxed = ssbKeys.secretBox({hello: 'world'}, key);
+  t.deepEqual(ssbKeys.secretUnbox(boxed, key), {hello: 'world'});
+  let shares = split(key);
+
+  t.notOk(inMemory(shares), 'no copy of the secret box key');
+  t.end();
+});